@@ -1,4 +1,9 @@
-use clap::{Parser, Subcommand};
+use crate::retry::Backoff;
+use crate::shred::ShredPattern;
+use crate::utils::parse_duration;
+use clap::{Parser, Subcommand, value_parser};
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(
@@ -28,6 +33,11 @@ pub enum Commands {
         #[arg(short = 'e', long)]
         disable_elevate: bool,
 
+        /// Allow deleting drive roots, the home directory, the current directory,
+        /// their ancestors and the Windows directory
+        #[arg(long)]
+        no_preserve_root: bool,
+
         /// Delay in ms when retrying to delete a file, after killing processes holding a lock
         #[arg(short = 'd', long, default_value = "50")]
         retry_delay: u64,
@@ -35,6 +45,150 @@ pub enum Commands {
         /// Number of retries when deleting a locked file
         #[arg(short = 'n', long, default_value = "10")]
         max_retries: u32,
+
+        /// How the delay between retries grows
+        #[arg(long, value_enum, default_value_t = Backoff::Fixed)]
+        backoff: Backoff,
+
+        /// Longest delay in ms between retries, however far the backoff has grown
+        #[arg(long, default_value = "10000")]
+        max_retry_delay: u64,
+
+        /// Fraction of each retry delay to randomly take off, from 0.0 to 1.0
+        #[arg(long, default_value = "0.0", value_parser = parse_fraction)]
+        retry_jitter: f64,
+
+        /// Number of retries for entries held open by processes, instead of --max-retries
+        #[arg(long)]
+        locked_retries: Option<u32>,
+
+        /// Number of retries for entries that cannot be deleted without elevation,
+        /// instead of --max-retries
+        #[arg(long)]
+        permission_retries: Option<u32>,
+
+        /// Number of retries for entries changed concurrently by something else,
+        /// instead of --max-retries
+        #[arg(long)]
+        racing_retries: Option<u32>,
+
+        /// Stop retrying once the whole deletion has taken this long, e.g. 30s or 5m,
+        /// reporting what remains and exiting with code 3
+        #[arg(long, value_parser = parse_duration, overrides_with = "timeout")]
+        timeout: Option<Duration>,
+
+        /// Stop retrying a file or directory once deleting it has taken this long, e.g. 10s
+        #[arg(long, value_parser = parse_duration)]
+        path_timeout: Option<Duration>,
+
+        /// Go on with the remaining files and directories after one fails, reporting every
+        /// failure at the end
+        #[arg(long)]
+        keep_going: bool,
+
+        /// Also kill the child processes of processes holding a lock, children first
+        #[arg(long)]
+        kill_tree: bool,
+
+        /// Restart the processes that were killed once the deletion has finished
+        #[arg(long)]
+        restart_killed: bool,
+
+        /// Suspend processes holding a lock instead of killing them, resuming them afterwards.
+        /// With --close-handles, also close their handles to the target if it is still locked
+        #[arg(long, conflicts_with_all = ["kill_tree", "restart_killed"])]
+        suspend: bool,
+
+        /// Experimental: close the handles to the target inside processes holding a lock,
+        /// falling back to killing them
+        #[arg(long)]
+        close_handles: bool,
+
+        /// Report what would be deleted or moved and which processes hold locks, without
+        /// deleting anything or acting on those processes
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Keep entries inside directories matching this glob, and their ancestors.
+        /// Can be given multiple times
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
+
+        /// Only delete entries inside directories matching this glob.
+        /// Can be given multiple times
+        #[arg(long, value_name = "GLOB")]
+        include: Vec<String>,
+
+        /// Delete everything inside the directories, keeping the directories themselves
+        #[arg(long)]
+        contents_only: bool,
+
+        /// Keep mount points of other volumes inside the directories, with their contents,
+        /// instead of deleting across volumes
+        #[arg(long, conflicts_with_all = ["trash", "quarantine", "background"])]
+        one_file_system: bool,
+
+        /// Delete the contents of directories that symlinks and junctions point to,
+        /// instead of only removing the links. Links to protected directories, or back
+        /// into a directory already being deleted, are only removed
+        #[arg(long, conflicts_with_all = ["trash", "quarantine", "background"])]
+        follow_links: bool,
+
+        /// Move the files and directories to the Recycle Bin instead of deleting them.
+        /// Fails on volumes without a Recycle Bin, like network shares
+        #[arg(long, conflicts_with_all = ["exclude", "include", "contents_only"])]
+        trash: bool,
+
+        /// Move the files and directories into the fops quarantine instead of deleting them,
+        /// so they can be restored with `fops restore`
+        #[arg(long, conflicts_with_all = ["exclude", "include", "contents_only", "trash"])]
+        quarantine: bool,
+
+        /// Rename the files and directories out of the way and delete them in the background,
+        /// returning as soon as their names are free
+        #[arg(
+            long,
+            conflicts_with_all = ["exclude", "include", "contents_only", "trash", "quarantine"]
+        )]
+        background: bool,
+
+        /// Overwrite the contents of files before deleting them
+        #[arg(long, conflicts_with_all = ["trash", "quarantine", "background"])]
+        shred: bool,
+
+        /// Number of times to overwrite the contents of files when shredding, at least 1
+        #[arg(long, default_value = "1", value_parser = value_parser!(u32).range(1..), requires = "shred")]
+        shred_passes: u32,
+
+        /// What to overwrite the contents of files with when shredding
+        #[arg(long, value_enum, default_value_t = ShredPattern::Random, requires = "shred")]
+        shred_pattern: ShredPattern,
+
+        /// Number of worker threads deleting entries in parallel when retrying,
+        /// or 0 for one per CPU
+        #[arg(short = 'j', long, default_value = "0")]
+        jobs: usize,
+
+        /// Write what was done to this file, for the process that relaunched fops elevated
+        #[arg(long, hide = true)]
+        elevated_result: Option<PathBuf>,
+
+        /// Record the processes killed in the elevated result, for the process that relaunched
+        /// fops elevated to restart them
+        #[arg(long, hide = true, requires = "elevated_result")]
+        capture_killed: bool,
+    },
+
+    /// Restore a quarantined file or directory to where it was
+    Restore {
+        /// Id of the quarantined item, as printed when it was quarantined
+        id: String,
+    },
+
+    /// Manage the fops quarantine
+    Quarantine {
+        #[command(subcommand)]
+        command: QuarantineCommands,
     },
 
     /// Uses lock detection to output processes using a file or directory
@@ -43,3 +197,23 @@ pub enum Commands {
         file_or_directory: String,
     },
 }
+
+#[derive(Subcommand)]
+pub enum QuarantineCommands {
+    /// List the quarantined items, oldest first
+    List,
+
+    /// Permanently delete quarantined items
+    Purge {
+        /// Only purge items quarantined at least this long ago, e.g. 30s, 15m, 12h or 7d
+        #[arg(long, value_parser = parse_duration, default_value = "0s")]
+        older_than: Duration,
+    },
+}
+
+fn parse_fraction(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(fraction) if (0.0..=1.0).contains(&fraction) => Ok(fraction),
+        _ => Err(format!("'{}' is not a number from 0.0 to 1.0", value)),
+    }
+}
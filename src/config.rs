@@ -1,23 +1,136 @@
+use crate::filter::PathFilter;
+use crate::retry::RetryPolicies;
+use crate::shred::ShredPattern;
+use std::time::Duration;
+
+/// What to do with processes holding a lock on a file or directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LockHolderAction {
+    /// Terminate the processes.
+    #[default]
+    Kill,
+    /// Suspend the processes, then resume them once the operation has finished, so they keep
+    /// their state. Their handles are left open unless
+    /// [`ForceOpsConfig::close_handles_when_suspended`] is set.
+    Suspend,
+    /// Close the handles the processes hold to the target, killing them if that fails.
+    /// This is experimental, as the processes may fail when they next use the handles.
+    CloseHandles,
+}
+
+impl LockHolderAction {
+    /// Describes the action in log messages, e.g. "Found 1 process to try to kill".
+    pub fn verb(&self) -> &'static str {
+        match self {
+            LockHolderAction::Kill => "kill",
+            LockHolderAction::Suspend => "suspend",
+            LockHolderAction::CloseHandles => "close handles of",
+        }
+    }
+}
+
 /// Configuration for ForceOps operations
 #[derive(Debug, Clone)]
 pub struct ForceOpsConfig {
-    /// The number of retries when performing an operation.
-    /// For example, five retries equals six total attempts.
-    pub max_retries: u32,
+    /// How many times to retry an operation and how long to wait before each retry,
+    /// for each class of error.
+    /// Replaces `max_retries` and `retry_delay_ms`, which [`RetryPolicies::fixed`] maps to.
+    pub retry_policy: RetryPolicies,
+
+    /// How long the deleter may spend on all of its operations, counted from when it was
+    /// created, after which it stops retrying. None for no limit.
+    pub timeout: Option<Duration>,
+
+    /// How long each target may take, after which the deleter stops retrying it.
+    /// None for no limit.
+    pub path_timeout: Option<Duration>,
 
-    /// The time to wait in milliseconds before retrying the operation.
-    pub retry_delay_ms: u64,
+    /// Whether to go on with the remaining targets after one fails, returning every failure
+    /// instead of stopping at the first.
+    pub keep_going: bool,
+
+    /// Whether to refuse deleting drive roots, the home directory, the current directory,
+    /// their ancestors and the Windows directory.
+    pub preserve_root: bool,
 
     /// Whether to disable auto-elevation when permission errors occur.
     pub disable_elevate: bool,
+
+    /// What to do with processes holding a lock.
+    pub lock_holder_action: LockHolderAction,
+
+    /// Whether to also kill the descendants of processes holding a lock.
+    pub kill_process_tree: bool,
+
+    /// Whether to close the handles suspended processes hold to the target when it is still
+    /// locked after suspending them. Only used with [`LockHolderAction::Suspend`].
+    pub close_handles_when_suspended: bool,
+
+    /// Whether to record killed processes so they can be restarted after the operation.
+    pub restart_killed: bool,
+
+    /// Which entries to delete inside directories.
+    pub filter: PathFilter,
+
+    /// Whether to delete only the contents of directories, keeping the directories themselves.
+    pub contents_only: bool,
+
+    /// Whether to keep mount points inside directories, with their contents and ancestors,
+    /// instead of deleting across volumes. Lock holders inside them are left alone.
+    pub one_file_system: bool,
+
+    /// Whether to delete the contents of directories that symlinks and junctions point to,
+    /// instead of only removing the links. Links are never followed to their own ancestors.
+    pub follow_links: bool,
+
+    /// Whether to move targets to the Recycle Bin instead of deleting them.
+    /// Targets are moved as a whole, ignoring `filter` and `contents_only`.
+    pub trash: bool,
+
+    /// Whether to move targets into the fops quarantine instead of deleting them, so they
+    /// can be restored. Targets are moved as a whole, ignoring `filter` and `contents_only`.
+    pub quarantine: bool,
+
+    /// Whether to rename targets to tombstones next to them instead of deleting them, leaving
+    /// the tombstones to be deleted later. Targets are renamed as a whole, ignoring `filter`
+    /// and `contents_only`.
+    pub tombstone: bool,
+
+    /// Number of times to overwrite the contents of files before deleting them,
+    /// or 0 to delete them without overwriting.
+    pub shred_passes: u32,
+
+    /// What to overwrite the contents of files with when shredding.
+    pub shred_pattern: ShredPattern,
+
+    /// Number of worker threads deleting entries in parallel when retrying,
+    /// or 0 for one per CPU.
+    pub jobs: usize,
 }
 
 impl Default for ForceOpsConfig {
     fn default() -> Self {
         Self {
-            max_retries: 10,
-            retry_delay_ms: 50,
+            retry_policy: RetryPolicies::default(),
+            timeout: None,
+            path_timeout: None,
+            keep_going: false,
+            preserve_root: true,
             disable_elevate: false,
+            lock_holder_action: LockHolderAction::Kill,
+            kill_process_tree: false,
+            close_handles_when_suspended: false,
+            restart_killed: false,
+            filter: PathFilter::default(),
+            contents_only: false,
+            one_file_system: false,
+            follow_links: false,
+            trash: false,
+            quarantine: false,
+            tombstone: false,
+            shred_passes: 0,
+            shred_pattern: ShredPattern::Random,
+            jobs: 0,
         }
     }
 }
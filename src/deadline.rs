@@ -0,0 +1,42 @@
+//! Deadlines bounding how long an operation may keep retrying
+
+use std::time::{Duration, Instant};
+
+/// A point in time after which an operation gives up, or no limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Deadline(Option<Instant>);
+
+impl Deadline {
+    /// No limit.
+    pub const NONE: Deadline = Deadline(None);
+
+    /// A deadline `timeout` from now, or no limit without a timeout.
+    pub fn after(timeout: Option<Duration>) -> Self {
+        Deadline(timeout.and_then(|t| Instant::now().checked_add(t)))
+    }
+
+    /// Whichever of two deadlines comes first.
+    pub fn earliest(self, other: Deadline) -> Deadline {
+        match (self.0, other.0) {
+            (Some(a), Some(b)) => Deadline(Some(a.min(b))),
+            (a, b) => Deadline(a.or(b)),
+        }
+    }
+
+    /// Whether the deadline has passed.
+    pub fn is_expired(&self) -> bool {
+        self.0.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// The time left until the deadline, or none without a limit.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.0
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// The shorter of `duration` and the time left, for waits that must not overrun the deadline.
+    pub fn limit(&self, duration: Duration) -> Duration {
+        self.remaining()
+            .map_or(duration, |remaining| duration.min(remaining))
+    }
+}
@@ -1,200 +1,1094 @@
 //! File and directory deletion with retry logic and lock detection
 
-use crate::config::ForceOpsConfig;
-use crate::lock_checker::{self, LockCheckError, ProcessInfo};
-use crate::process;
-use crate::utils::{is_symlink, mark_as_not_readonly};
-use anyhow::{Result, anyhow};
+use crate::config::{ForceOpsConfig, LockHolderAction};
+use crate::deadline::Deadline;
+use crate::dir_handle::{DirectoryHandle, file_name};
+use crate::error_classifier::{ErrorClass, classify};
+use crate::fast_delete;
+use crate::filter::FilterDecision;
+use crate::handles;
+use crate::lock_checker::{self, LockCheckError, ProcessInfo, format_processes};
+use crate::process::{self, KillOutcome};
+use crate::quarantine::{self, QuarantineEntry};
+use crate::report::{DeleteReport, DryRunReport, LockedEntry, PlannedAction, PlannedEntry};
+use crate::restart::{self, LaunchInfo, RestartOutcome};
+use crate::safety::{self, ProtectedPath};
+use crate::shred;
+use crate::suspend::{self, SuspendedProcess};
+use crate::tombstone;
+use crate::trash;
+use crate::utils::{EntryKind, can_follow_link, entry_exists, is_mount_point};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
 use std::thread;
-use std::time::Duration;
+use std::time::Instant;
+use thiserror::Error;
 use tracing::{info, warn};
 
-// Use parallel remove_dir_all for fast directory deletion
-use remove_dir_all::remove_dir_all as fast_remove_dir_all;
+/// Errors that can occur while deleting files and directories
+#[derive(Error, Debug)]
+pub enum DeleteError {
+    #[error("Cannot remove '{}'. No such file or directory", .path.display())]
+    NotFound { path: PathBuf },
+
+    #[error("Cannot remove '{}'. Permission denied: {source}", .path.display())]
+    PermissionDenied { path: PathBuf, source: io::Error },
+
+    #[error(
+        "Cannot remove '{}'. Locked by {} {} [{}]: {source}",
+        .path.display(),
+        .processes.len(),
+        if .processes.len() == 1 { "process" } else { "processes" },
+        format_processes(.processes)
+    )]
+    LockedByProcesses {
+        path: PathBuf,
+        /// The processes holding the lock when the last retry failed.
+        processes: Vec<ProcessInfo>,
+        source: io::Error,
+    },
+
+    #[error("Failed to delete '{}' after {retries} retries", .path.display())]
+    RetriesExhausted { path: PathBuf, retries: u32 },
+
+    #[error(
+        "Timed out deleting '{}'. Held by {} {} [{}]",
+        .path.display(),
+        .processes.len(),
+        if .processes.len() == 1 { "process" } else { "processes" },
+        format_processes(.processes)
+    )]
+    TimedOut {
+        path: PathBuf,
+        /// The processes holding the path when the deadline passed.
+        processes: Vec<ProcessInfo>,
+    },
+
+    #[error("Cannot remove '{}'. Read-only file system: {source}", .path.display())]
+    ReadOnlyFilesystem { path: PathBuf, source: io::Error },
+
+    #[error("Cannot remove '{}'. Resource is busy: {source}", .path.display())]
+    Busy { path: PathBuf, source: io::Error },
+
+    #[error("Cannot remove '{}'. Directory not empty: {source}", .path.display())]
+    DirectoryNotEmpty { path: PathBuf, source: io::Error },
+
+    #[error("Cannot remove '{}': {source}", .path.display())]
+    Io { path: PathBuf, source: io::Error },
+
+    #[error("Cannot quarantine '{}': {source}", .path.display())]
+    Quarantine { path: PathBuf, source: io::Error },
+
+    #[error("Refusing to remove '{}', as it is {reason}", .path.display())]
+    Protected {
+        path: PathBuf,
+        reason: ProtectedPath,
+    },
+}
+
+impl DeleteError {
+    /// Create the error for an I/O error that occurred while deleting `path`.
+    pub fn from_io(path: &Path, source: io::Error) -> Self {
+        let path = path.to_path_buf();
+
+        // ERROR_WRITE_PROTECT
+        if source.raw_os_error() == Some(19) {
+            return DeleteError::ReadOnlyFilesystem { path, source };
+        }
+
+        match source.kind() {
+            io::ErrorKind::NotFound => DeleteError::NotFound { path },
+            io::ErrorKind::PermissionDenied => DeleteError::PermissionDenied { path, source },
+            io::ErrorKind::ReadOnlyFilesystem => DeleteError::ReadOnlyFilesystem { path, source },
+            io::ErrorKind::ResourceBusy => DeleteError::Busy { path, source },
+            io::ErrorKind::DirectoryNotEmpty => DeleteError::DirectoryNotEmpty { path, source },
+            _ if classify(&source) == ErrorClass::Locked => DeleteError::LockedByProcesses {
+                path,
+                processes: Vec::new(),
+                source,
+            },
+            _ => DeleteError::Io { path, source },
+        }
+    }
+
+    /// Create the error for the last failed attempt to delete `path`, finding the processes
+    /// still holding it if it is locked or the deadline has passed.
+    fn after_retries<F>(
+        path: &Path,
+        source: io::Error,
+        retries: u32,
+        deadline: Deadline,
+        get_processes: F,
+    ) -> Self
+    where
+        F: Fn(Deadline) -> Vec<ProcessInfo>,
+    {
+        if deadline.is_expired() {
+            return DeleteError::TimedOut {
+                path: path.to_path_buf(),
+                processes: get_processes(Deadline::NONE),
+            };
+        }
+
+        if classify(&source) == ErrorClass::Racing {
+            return DeleteError::RetriesExhausted {
+                path: path.to_path_buf(),
+                retries,
+            };
+        }
+
+        match DeleteError::from_io(path, source) {
+            DeleteError::LockedByProcesses { path, source, .. } => DeleteError::LockedByProcesses {
+                path,
+                processes: get_processes(Deadline::NONE),
+                source,
+            },
+            error => error,
+        }
+    }
+
+    /// The path that could not be deleted.
+    pub fn path(&self) -> &Path {
+        match self {
+            DeleteError::NotFound { path }
+            | DeleteError::PermissionDenied { path, .. }
+            | DeleteError::LockedByProcesses { path, .. }
+            | DeleteError::RetriesExhausted { path, .. }
+            | DeleteError::TimedOut { path, .. }
+            | DeleteError::ReadOnlyFilesystem { path, .. }
+            | DeleteError::Busy { path, .. }
+            | DeleteError::DirectoryNotEmpty { path, .. }
+            | DeleteError::Io { path, .. }
+            | DeleteError::Quarantine { path, .. }
+            | DeleteError::Protected { path, .. } => path,
+        }
+    }
+
+    /// Whether the deletion could succeed when retried as an elevated process,
+    /// which can delete protected files and kill processes of other users.
+    pub fn needs_elevation(&self) -> bool {
+        matches!(
+            self,
+            DeleteError::PermissionDenied { .. } | DeleteError::LockedByProcesses { .. }
+        )
+    }
+}
+
+/// Several targets that could not be deleted, reported together
+#[derive(Error, Debug)]
+#[error(
+    "Failed to delete {} {}:{}",
+    .0.len(),
+    if .0.len() == 1 { "target" } else { "targets" },
+    .0.iter().map(|e| format!("\n  {e}")).collect::<String>()
+)]
+pub struct DeleteFailures(pub Vec<DeleteError>);
+
+impl DeleteFailures {
+    /// Whether any of the deletions could succeed when retried as an elevated process.
+    pub fn needs_elevation(&self) -> bool {
+        self.0.iter().any(DeleteError::needs_elevation)
+    }
+
+    /// Whether any of the deletions stopped at the deadline.
+    pub fn timed_out(&self) -> bool {
+        self.0
+            .iter()
+            .any(|e| matches!(e, DeleteError::TimedOut { .. }))
+    }
+
+    /// Whether any of the targets was refused as protected.
+    pub fn protected(&self) -> bool {
+        self.0
+            .iter()
+            .any(|e| matches!(e, DeleteError::Protected { .. }))
+    }
+}
+
+/// What deleting several targets did, and the targets that could not be deleted
+#[derive(Debug, Default)]
+pub struct DeleteAllOutcome {
+    /// What was deleted, across all targets.
+    pub report: DeleteReport,
+    /// Why targets could not be deleted, in the order the targets were given.
+    pub failures: Vec<DeleteError>,
+    /// Targets that were not attempted, after stopping at a failure without `keep_going`.
+    pub not_attempted: Vec<PathBuf>,
+}
 
 /// Handles deletion of files and directories with retry logic and process killing.
 pub struct FileAndDirectoryDeleter {
     config: ForceOpsConfig,
+    /// Processes killed so far that can be restarted, if `restart_killed` is enabled.
+    killed_processes: Mutex<Vec<LaunchInfo>>,
+    /// Processes suspended so far, resumed when dropped.
+    suspended_processes: Mutex<Vec<SuspendedProcess>>,
+    /// Worker pool for the retry-based deletion of directories.
+    pool: OnceLock<ThreadPool>,
+    /// When every operation of this deleter stops retrying, from `timeout`.
+    deadline: Deadline,
+    /// What would be done, recorded instead of doing it, for a dry run.
+    dry_run: Option<Mutex<DryRunReport>>,
 }
 
 impl FileAndDirectoryDeleter {
     pub fn new(config: ForceOpsConfig) -> Self {
-        Self { config }
+        Self {
+            deadline: Deadline::after(config.timeout),
+            config,
+            killed_processes: Mutex::new(Vec::new()),
+            suspended_processes: Mutex::new(Vec::new()),
+            pool: OnceLock::new(),
+            dry_run: None,
+        }
+    }
+
+    /// When every operation of this deleter stops retrying.
+    pub fn deadline(&self) -> Deadline {
+        self.deadline
+    }
+
+    /// When an operation on a target starting now stops retrying.
+    fn path_deadline(&self) -> Deadline {
+        self.deadline
+            .earliest(Deadline::after(self.config.path_timeout))
+    }
+
+    /// Resume the processes suspended by this deleter, if the lock holder action is
+    /// [`LockHolderAction::Suspend`]. This also happens when the deleter is dropped.
+    pub fn resume_suspended_processes(&self) {
+        self.suspended_processes.lock().unwrap().clear();
+    }
+
+    /// Restart the processes killed by this deleter, if `restart_killed` is enabled.
+    /// Each process is restarted at most once.
+    pub fn restart_killed_processes(&self) -> Vec<RestartOutcome> {
+        restart::restart_processes(&self.take_killed_processes())
+    }
+
+    /// Take the processes killed by this deleter, if `restart_killed` is enabled, to restart
+    /// them elsewhere, e.g. in the process that relaunched fops elevated.
+    pub fn take_killed_processes(&self) -> Vec<LaunchInfo> {
+        std::mem::take(&mut *self.killed_processes.lock().unwrap())
     }
 
-    /// Delete a file or a folder, not following symlinks.
+    /// Delete a file or a folder, not following symlinks unless `follow_links` is enabled.
     /// If the delete fails, it will attempt to find processes using the file or directory.
-    pub fn delete_file_or_directory(&self, path: &Path, force: bool) -> Result<()> {
-        if path.is_file() {
-            return self.delete_file(path);
+    pub fn delete_file_or_directory(
+        &self,
+        path: &Path,
+        force: bool,
+    ) -> Result<DeleteReport, DeleteError> {
+        self.check_protected(path)?;
+
+        if self.deadline.is_expired() && entry_exists(path) {
+            return Err(DeleteError::TimedOut {
+                path: path.to_path_buf(),
+                processes: Vec::new(),
+            });
         }
 
-        if path.is_dir() {
-            return self.delete_directory(path);
+        if self.is_shredding() && shred::is_copy_on_write(path) {
+            warn!(
+                "'{}' is on a copy-on-write file system, so overwriting its files may leave their contents on disk.",
+                path.display()
+            );
+        }
+
+        if self.config.trash && entry_exists(path) {
+            return self.move_to_trash(path);
+        }
+
+        if self.config.quarantine && entry_exists(path) {
+            return self.move_to_quarantine(path);
+        }
+
+        if self.config.tombstone && entry_exists(path) {
+            return self.move_to_tombstone(path);
+        }
+
+        match EntryKind::of(path) {
+            Some(kind) if kind.is_directory() => return self.delete_directory(path),
+            Some(_) => return self.delete_file(path),
+            None => {}
         }
 
         if !force {
-            return Err(anyhow!(
-                "Cannot remove '{}'. No such file or directory",
-                path.display()
-            ));
+            return Err(DeleteError::NotFound {
+                path: path.to_path_buf(),
+            });
         }
 
-        Ok(())
+        Ok(DeleteReport::new())
     }
 
-    /// Delete a single file with retry logic.
-    pub fn delete_file(&self, path: &Path) -> Result<()> {
-        for attempt in 1..=self.config.max_retries + 1 {
-            // Try to remove read-only attribute
-            let _ = mark_as_not_readonly(path);
-
-            match fs::remove_file(path) {
-                Ok(()) => return Ok(()),
-                Err(_e) if !path.exists() => return Ok(()), // File was deleted by something else
-                Err(e) if is_io_or_permission_error(&e) => {
-                    let get_processes = || -> Vec<ProcessInfo> {
-                        match lock_checker::get_locking_processes(&[path]) {
-                            Ok(procs) => procs,
-                            Err(LockCheckError::GetList { code: 5, message }) => {
-                                warn!(
-                                    "Ignored exception: Failed to get entries (retry 0). (RmGetList() error 5: {})",
-                                    message
-                                );
-                                Vec::new()
-                            }
-                            Err(_) => Vec::new(),
-                        }
-                    };
-
-                    if self.kill_processes_and_log_info(false, attempt, path, get_processes) {
-                        return Err(anyhow!("{}", e));
+    /// Delete several files or folders with `delete_file_or_directory`, merging their reports.
+    /// Stops at the first target that fails, unless `keep_going` is enabled.
+    pub fn delete_all(&self, paths: &[PathBuf], force: bool) -> DeleteAllOutcome {
+        let mut outcome = DeleteAllOutcome {
+            report: DeleteReport::new(),
+            ..Default::default()
+        };
+
+        for (i, path) in paths.iter().enumerate() {
+            match self.delete_file_or_directory(path, force) {
+                Ok(report) => outcome.report.merge(report),
+                Err(e) => {
+                    outcome.failures.push(e);
+                    if !self.config.keep_going {
+                        outcome.not_attempted = paths[i + 1..].to_vec();
+                        break;
                     }
                 }
-                Err(e) => return Err(anyhow!("{}", e)),
             }
         }
+        outcome
+    }
 
-        Err(anyhow!(
-            "Failed to delete file '{}' after {} retries",
-            path.display(),
-            self.config.max_retries
-        ))
+    /// Refuse targets such as drive roots, unless `preserve_root` is disabled.
+    fn check_protected(&self, path: &Path) -> Result<(), DeleteError> {
+        if !self.config.preserve_root {
+            return Ok(());
+        }
+        match safety::check_protected(path) {
+            Some(reason) => Err(DeleteError::Protected {
+                path: path.to_path_buf(),
+                reason,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Run a deletion, collecting what it did into a report.
+    fn with_report<F>(&self, delete: F) -> Result<DeleteReport, DeleteError>
+    where
+        F: FnOnce(&mut DeleteReport) -> Result<(), DeleteError>,
+    {
+        let start = Instant::now();
+        let mut report = DeleteReport::new();
+        delete(&mut report)?;
+        report.elapsed = start.elapsed();
+        Ok(report)
+    }
+
+    /// Report what `delete_file_or_directory` would do, without deleting anything
+    /// or acting on the processes holding locks. The deletion runs the same way, except that
+    /// each entry is checked for locks and recorded instead of deleted or moved.
+    pub fn dry_run(&self, path: &Path, force: bool) -> Result<DryRunReport, DeleteError> {
+        let deleter = Self {
+            dry_run: Some(Mutex::default()),
+            ..Self::new(self.config.clone())
+        };
+        let report = deleter.delete_file_or_directory(path, force)?;
+
+        let mut plan = deleter.dry_run.unwrap().into_inner().unwrap();
+        plan.entries.sort_by(|a, b| a.path.cmp(&b.path));
+        plan.excluded = report.excluded;
+        plan.mount_points = report.mount_points;
+        Ok(plan)
+    }
+
+    /// For a dry run, record what would be done with a target as a whole, instead of doing it.
+    /// Returns whether it was recorded.
+    fn plan_target(&self, path: &Path, action: PlannedAction) -> Result<bool, DeleteError> {
+        if self.dry_run.is_none() {
+            return Ok(false);
+        }
+        let parent = open_parent(path)?;
+        Ok(self.plan(&parent, path, action))
+    }
+
+    /// For a dry run, record what would be done with an entry instead of doing it, checking
+    /// through the handle of its parent whether it could be done right now and finding the
+    /// processes holding it if not. Returns whether it was recorded.
+    fn plan(&self, parent: &DirectoryHandle, path: &Path, action: PlannedAction) -> bool {
+        let Some(plan) = &self.dry_run else {
+            return false;
+        };
+        let mut entry_plan = DryRunReport::default();
+        entry_plan.entries.push(PlannedEntry {
+            path: path.to_path_buf(),
+            action,
+        });
+
+        match parent.probe_remove(file_name(path)) {
+            Ok(()) => {}
+            Err(e) if classify(&e) == ErrorClass::Locked => {
+                let processes = if EntryKind::of(path).is_some_and(EntryKind::is_directory) {
+                    get_directory_locking_processes(path, Deadline::NONE)
+                } else {
+                    get_file_locking_processes(path)
+                };
+
+                if !crate::elevation::is_process_elevated()
+                    && processes.iter().any(|p| !process::can_terminate(p))
+                {
+                    entry_plan.needs_elevation = true;
+                }
+
+                entry_plan.locked_entries.push(LockedEntry {
+                    path: path.to_path_buf(),
+                    processes,
+                });
+            }
+            Err(e) if classify(&e) == ErrorClass::NeedsElevation => {
+                if !crate::elevation::is_process_elevated() {
+                    entry_plan.needs_elevation = true;
+                }
+            }
+            Err(_) => {}
+        }
+
+        plan.lock().unwrap().merge(entry_plan);
+        true
+    }
+
+    /// Delete a single file with retry logic.
+    pub fn delete_file(&self, path: &Path) -> Result<DeleteReport, DeleteError> {
+        self.check_protected(path)?;
+        let deadline = self.path_deadline();
+        self.with_report(|report| {
+            let parent = open_parent(path)?;
+            self.delete_file_with_report(&parent, path, deadline, report)
+        })
+    }
+
+    fn delete_file_with_report(
+        &self,
+        parent: &DirectoryHandle,
+        path: &Path,
+        deadline: Deadline,
+        report: &mut DeleteReport,
+    ) -> Result<(), DeleteError> {
+        if self.plan(parent, path, PlannedAction::Delete) {
+            return Ok(());
+        }
+        let size = fs::symlink_metadata(path).map(|m| m.len()).unwrap_or(0);
+        let get_processes = |_| get_file_locking_processes(path);
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match self
+                .shred_file(parent, path)
+                .and_then(|()| parent.remove(file_name(path)))
+            {
+                Ok(()) => {
+                    report.entries_deleted += 1;
+                    report.bytes_freed += size;
+                    return Ok(());
+                }
+                Err(_e) if !entry_exists(path) => return Ok(()), // File was deleted by something else
+                Err(e) if should_retry_file(&e) => {
+                    if self.kill_processes_and_log_info(
+                        false,
+                        attempt,
+                        path,
+                        &e,
+                        deadline,
+                        get_processes,
+                        report,
+                    ) {
+                        return Err(DeleteError::after_retries(
+                            path,
+                            e,
+                            attempt - 1,
+                            deadline,
+                            get_processes,
+                        ));
+                    }
+                }
+                Err(e) => return Err(DeleteError::from_io(path, e)),
+            }
+        }
     }
 
     /// Delete a directory recursively with retry logic.
-    pub fn delete_directory(&self, path: &Path) -> Result<()> {
-        if is_symlink(path) {
-            // For symlinks, just remove the symlink itself (not its contents)
-            let _ = mark_as_not_readonly(path);
-            return fs::remove_dir(path).map_err(|e| anyhow!("{}", e));
+    pub fn delete_directory(&self, path: &Path) -> Result<DeleteReport, DeleteError> {
+        self.check_protected(path)?;
+        let deadline = self.path_deadline();
+        self.with_report(|report| self.delete_root_directory(path, deadline, report))
+    }
+
+    /// Delete a directory given as a target, applying the filter and contents only mode
+    /// to the entries inside it.
+    fn delete_root_directory(
+        &self,
+        path: &Path,
+        deadline: Deadline,
+        report: &mut DeleteReport,
+    ) -> Result<(), DeleteError> {
+        let parent = open_parent(path)?;
+        if !self.descends_into(path) {
+            // A link has no contents of its own to delete
+            if self.config.contents_only {
+                return Ok(());
+            }
+            return self.delete_directory_with_report(&parent, path, deadline, report);
         }
 
-        // Try fast parallel deletion first
-        if fast_remove_dir_all(path).is_ok() {
+        let per_entry = self.config.contents_only || self.is_shredding();
+        if !per_entry && self.config.filter.is_empty() {
+            return self.delete_directory_with_report(&parent, path, deadline, report);
+        }
+
+        let included = self.config.filter.includes_root();
+        self.delete_filtered(path, &parent, path, included, deadline, report)?;
+        Ok(())
+    }
+
+    /// Move a file or directory to the Recycle Bin with retry logic.
+    pub fn move_to_trash(&self, path: &Path) -> Result<DeleteReport, DeleteError> {
+        self.check_protected(path)?;
+        let deadline = self.path_deadline();
+        self.with_report(|report| {
+            if self.plan_target(path, PlannedAction::Trash)? {
+                return Ok(());
+            }
+            let (entries, bytes) = measure_entry(path);
+            self.move_with_retry(path, deadline, report, || {
+                trash::move_to_trash(path).map(|()| false)
+            })?;
+            report.entries_deleted += entries;
+            report.bytes_freed += bytes;
+            Ok(())
+        })
+    }
+
+    /// Rename a file or directory to a tombstone next to it with retry logic, returning as
+    /// soon as its name is free. The tombstone is recorded in the report, to be deleted by
+    /// the caller, e.g. with `tombstone::delete_in_background`.
+    pub fn move_to_tombstone(&self, path: &Path) -> Result<DeleteReport, DeleteError> {
+        self.check_protected(path)?;
+        let deadline = self.path_deadline();
+        self.with_report(|report| {
+            if self.plan_target(path, PlannedAction::Tombstone)? {
+                return Ok(());
+            }
+            let tombstone = tombstone::tombstone_path(path);
+            self.move_with_retry(path, deadline, report, || {
+                fs::rename(path, &tombstone).map(|()| false)
+            })?;
+            report.tombstones.push(tombstone);
+            Ok(())
+        })
+    }
+
+    /// Move a file or directory into the quarantine with retry logic, recording it in a
+    /// manifest so it can be restored.
+    pub fn move_to_quarantine(&self, path: &Path) -> Result<DeleteReport, DeleteError> {
+        self.check_protected(path)?;
+        let deadline = self.path_deadline();
+        self.with_report(|report| self.move_to_quarantine_with_report(path, deadline, report))
+    }
+
+    fn move_to_quarantine_with_report(
+        &self,
+        path: &Path,
+        deadline: Deadline,
+        report: &mut DeleteReport,
+    ) -> Result<(), DeleteError> {
+        if self.plan_target(path, PlannedAction::Quarantine)? {
             return Ok(());
         }
+        let quarantine_error = |source| DeleteError::Quarantine {
+            path: path.to_path_buf(),
+            source,
+        };
+        let entry = QuarantineEntry::create().map_err(quarantine_error)?;
+        let item_path = entry.item_path();
+        let killed_before = report.killed_processes.len();
+        let (entries, bytes) = measure_entry(path);
+
+        match self.move_with_retry(path, deadline, report, || {
+            quarantine::rename_or_copy(path, &item_path)
+        }) {
+            Ok(true) => {}
+            Ok(false) => {
+                report.entries_deleted += entries;
+                report.bytes_freed += bytes;
+            }
+            Err(e) => {
+                entry.discard();
+                return Err(e);
+            }
+        }
+
+        let killed_processes = report.killed_processes[killed_before..]
+            .iter()
+            .filter(|k| k.outcome == KillOutcome::Killed)
+            .map(|k| k.process_info.clone())
+            .collect();
+        let manifest = entry
+            .finish(path, killed_processes)
+            .map_err(quarantine_error)?;
+        report.quarantined.insert(path.to_path_buf(), manifest.id);
+        Ok(())
+    }
+
+    /// Move a file or directory as a whole with retry logic. `move_target` returns whether
+    /// it copied the target instead, in which case the original is deleted afterwards.
+    /// Returns whether the original was deleted, and so already counted in the report.
+    fn move_with_retry<F>(
+        &self,
+        path: &Path,
+        deadline: Deadline,
+        report: &mut DeleteReport,
+        move_target: F,
+    ) -> Result<bool, DeleteError>
+    where
+        F: Fn() -> io::Result<bool>,
+    {
+        let is_directory = EntryKind::of(path) == Some(EntryKind::Directory);
+        let get_processes = |deadline| {
+            if is_directory {
+                get_tree_locking_processes(path, deadline)
+            } else {
+                get_file_locking_processes(path)
+            }
+        };
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match move_target() {
+                Ok(false) => return Ok(false),
+                Ok(true) if is_directory => {
+                    let parent = open_parent(path)?;
+                    self.delete_directory_with_report(&parent, path, deadline, report)?;
+                    return Ok(true);
+                }
+                Ok(true) => {
+                    let parent = open_parent(path)?;
+                    self.delete_file_with_report(&parent, path, deadline, report)?;
+                    return Ok(true);
+                }
+                Err(_) if !entry_exists(path) => {
+                    return Err(DeleteError::NotFound {
+                        path: path.to_path_buf(),
+                    });
+                }
+                Err(e) if should_retry_file(&e) => {
+                    if self.kill_processes_and_log_info(
+                        is_directory,
+                        attempt,
+                        path,
+                        &e,
+                        deadline,
+                        get_processes,
+                        report,
+                    ) {
+                        return Err(DeleteError::after_retries(
+                            path,
+                            e,
+                            attempt - 1,
+                            deadline,
+                            get_processes,
+                        ));
+                    }
+                }
+                Err(e) => return Err(DeleteError::from_io(path, e)),
+            }
+        }
+    }
+
+    fn is_shredding(&self) -> bool {
+        self.config.shred_passes > 0
+    }
 
-        // Fast path failed - check if directory still exists
-        if !path.exists() {
+    /// Overwrite a file before it is deleted, if shredding, opening it through the handle of
+    /// its parent. Symlinks are not followed.
+    fn shred_file(&self, parent: &DirectoryHandle, path: &Path) -> io::Result<()> {
+        if !self.is_shredding() {
             return Ok(());
         }
+        match parent.open_file_for_overwrite(file_name(path))? {
+            Some(mut file) => shred::shred(
+                &mut file,
+                self.config.shred_passes,
+                self.config.shred_pattern,
+            ),
+            None => Ok(()),
+        }
+    }
 
-        // Fall back to slow path with per-file retry logic
+    /// Open a directory through the handle of its parent, so that a directory swapped for a
+    /// link is not listed through it.
+    fn open_directory(
+        &self,
+        parent: &DirectoryHandle,
+        directory: &Path,
+    ) -> io::Result<DirectoryHandle> {
+        parent.open_directory(file_name(directory), self.config.follow_links)
+    }
+
+    /// Whether to delete the contents of a directory before the directory itself: always for
+    /// directories, and for links to directories when following links.
+    fn descends_into(&self, path: &Path) -> bool {
+        match EntryKind::of(path) {
+            Some(EntryKind::Directory) => true,
+            Some(EntryKind::DirectoryLink) => self.config.follow_links && can_follow_link(path),
+            _ => false,
+        }
+    }
+
+    /// Whether an entry inside a target is a mount point to keep, when not crossing volumes.
+    fn skips_mount_point(&self, path: &Path) -> bool {
+        self.config.one_file_system && path.is_dir() && is_mount_point(path)
+    }
+
+    /// Whether a directory is the root of a contents only deletion, which must be kept.
+    fn keeps_root(&self, root: &Path, directory: &Path) -> bool {
+        self.config.contents_only && directory == root
+    }
+
+    fn delete_directory_with_report(
+        &self,
+        parent: &DirectoryHandle,
+        path: &Path,
+        deadline: Deadline,
+        report: &mut DeleteReport,
+    ) -> Result<(), DeleteError> {
+        if !self.descends_into(path) {
+            if self.plan(parent, path, PlannedAction::Delete) {
+                return Ok(());
+            }
+            // For symlinks, just remove the symlink itself (not its contents)
+            parent
+                .remove(file_name(path))
+                .map_err(|e| DeleteError::from_io(path, e))?;
+            report.entries_deleted += 1;
+            return Ok(());
+        }
+
+        // A dry run records each entry, which the fast path does not go through
+        if self.dry_run.is_some() {
+            return self.delete_directory_with_retry(parent, path, deadline, report);
+        }
+
+        // Try fast parallel deletion first, then fall back to the slow path with retry
+        // logic for only the entries it could not delete, and their ancestors.
         // This handles locked files/directories properly
-        self.delete_directory_with_retry(path)
+        let one_file_system = self.config.one_file_system;
+        let follow_links = self.config.follow_links;
+        let outcome = self
+            .pool()
+            .install(|| fast_delete::remove_tree(parent, path, one_file_system, follow_links));
+        report.entries_deleted += outcome.entries_deleted;
+        report.bytes_freed += outcome.bytes_freed;
+        report.mount_points.extend(outcome.mount_points);
+
+        if outcome.failures.is_empty() {
+            return Ok(());
+        }
+        self.delete_failed_entries(parent, path, outcome.failures, deadline, report)
     }
 
-    /// Delete directory with full retry logic including process killing.
-    fn delete_directory_with_retry(&self, path: &Path) -> Result<()> {
-        for attempt in 1..=self.config.max_retries + 1 {
-            // Delete contents first (if not a symlink)
-            if !is_symlink(path)
-                && let Err(e) = self.delete_files_in_folder_once(path)
+    /// Retry deleting the entries the fast path failed on, then their ancestors, deepest first.
+    fn delete_failed_entries(
+        &self,
+        parent: &DirectoryHandle,
+        directory: &Path,
+        failures: Vec<(PathBuf, io::Error)>,
+        deadline: Deadline,
+        report: &mut DeleteReport,
+    ) -> Result<(), DeleteError> {
+        info!(
+            "Could not delete {} {} in \"{}\" on the first attempt, retrying them",
+            failures.len(),
+            if failures.len() == 1 {
+                "entry"
+            } else {
+                "entries"
+            },
+            directory.display()
+        );
+
+        let failed_paths: Vec<PathBuf> = failures.into_iter().map(|(path, _)| path).collect();
+        self.retry_failed_paths(parent, directory, &failed_paths, deadline, report)
+    }
+
+    /// Retry the failed entries inside `directory`, then remove `directory` itself. Only the
+    /// directories on the way to a failed entry are opened, each through the handle of its
+    /// parent, and they are removed after the entries inside them.
+    fn retry_failed_paths(
+        &self,
+        parent: &DirectoryHandle,
+        directory: &Path,
+        failed_paths: &[PathBuf],
+        deadline: Deadline,
+        report: &mut DeleteReport,
+    ) -> Result<(), DeleteError> {
+        // The directory itself failed, e.g. as it could not be read, so retry all of it
+        if failed_paths.iter().any(|path| path == directory) {
+            return self.delete_directory_with_retry(parent, directory, deadline, report);
+        }
+
+        let handle = match self.open_directory(parent, directory) {
+            Ok(handle) => handle,
+            Err(_) if !entry_exists(directory) => return Ok(()),
+            Err(e) => return Err(DeleteError::from_io(directory, e)),
+        };
+
+        // Group the failed entries by the entry of this directory they are in
+        let mut children: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+        for path in failed_paths {
+            if let Ok(relative_path) = path.strip_prefix(directory)
+                && let Some(first) = relative_path.components().next()
             {
-                // If deleting contents fails, try to kill processes and retry
-                if attempt <= self.config.max_retries {
-                    let path_clone = path.to_path_buf();
-                    let get_processes = || -> Vec<ProcessInfo> {
-                        lock_checker::get_locking_processes_low_level(&path_clone)
-                            .unwrap_or_default()
-                    };
-                    self.kill_processes_and_log_info(true, attempt, path, get_processes);
-                    continue;
-                }
-                return Err(e);
+                children
+                    .entry(directory.join(first))
+                    .or_default()
+                    .push(path.clone());
             }
+        }
 
-            // Try to remove the directory itself
-            let _ = mark_as_not_readonly(path);
-            match fs::remove_dir(path) {
-                Ok(()) => return Ok(()),
-                Err(_) if !path.exists() => return Ok(()),
-                Err(e) if is_io_error(&e) => {
-                    let path_clone = path.to_path_buf();
-                    let get_processes = || -> Vec<ProcessInfo> {
-                        lock_checker::get_locking_processes_low_level(&path_clone)
-                            .unwrap_or_default()
-                    };
-
-                    if self.kill_processes_and_log_info(true, attempt, path, get_processes) {
-                        return Err(anyhow!("{}", e));
-                    }
-                }
-                Err(e) => return Err(anyhow!("{}", e)),
+        let mut failed_entries = Vec::new();
+        for (child, paths) in children {
+            if paths.iter().all(|path| *path == child) {
+                failed_entries.push(child);
+            } else {
+                self.retry_failed_paths(&handle, &child, &paths, deadline, report)?;
             }
         }
+        self.delete_entries_with_retry(&handle, &failed_entries, deadline, report)?;
 
-        Err(anyhow!(
-            "Failed to delete directory '{}' after {} retries",
-            path.display(),
-            self.config.max_retries
-        ))
+        drop(handle);
+        self.delete_directory_with_retry(parent, directory, deadline, report)
     }
 
-    /// Try to delete files in folder once, without retrying individual files.
-    fn delete_files_in_folder_once(&self, directory: &Path) -> Result<()> {
-        let entries = match fs::read_dir(directory) {
-            Ok(e) => e,
-            Err(_) if !directory.exists() => return Ok(()),
-            Err(e) => return Err(anyhow!("{}", e)),
+    /// Delete the entries of a directory selected by the filter, each with the fast path
+    /// first and retry logic after. Returns whether the directory was kept.
+    fn delete_filtered(
+        &self,
+        root: &Path,
+        parent: &DirectoryHandle,
+        directory: &Path,
+        included: bool,
+        deadline: Deadline,
+        report: &mut DeleteReport,
+    ) -> Result<bool, DeleteError> {
+        let handle = match self.open_directory(parent, directory) {
+            Ok(handle) => handle,
+            Err(_) if !entry_exists(directory) => return Ok(false),
+            Err(e) => return Err(DeleteError::from_io(directory, e)),
         };
+        let entries = handle
+            .entries()
+            .map_err(|e| DeleteError::from_io(directory, e))?;
+        let mut kept = false;
 
         for entry in entries {
-            let entry = entry?;
-            let path = entry.path();
-
-            if path.is_file() {
-                // Try fast delete, ignore errors (will retry at directory level)
-                let _ = mark_as_not_readonly(&path);
-                if let Err(e) = fs::remove_file(&path)
-                    && path.exists()
-                    && is_io_or_permission_error(&e)
-                {
-                    return Err(anyhow!("{}", e));
+            let path = directory.join(&entry.name);
+            let kind = entry.kind;
+            let relative_path = path.strip_prefix(root).unwrap_or(&path);
+
+            if self.skips_mount_point(&path) {
+                report.mount_points.insert(path);
+                kept = true;
+                continue;
+            }
+
+            // Unless following links, directory links are deleted as a single entry
+            let descend = self.descends_into(&path);
+            match self.config.filter.decide(relative_path, descend, included) {
+                // Deleting a directory wholesale would not overwrite its files
+                FilterDecision::Delete if descend && self.is_shredding() => {
+                    kept |= self.delete_filtered(root, &handle, &path, true, deadline, report)?;
+                }
+                FilterDecision::Delete if kind.is_directory() => {
+                    self.delete_directory_with_report(&handle, &path, deadline, report)?;
+                    kept |= contains_mount_point(report, &path);
+                }
+                FilterDecision::Delete => {
+                    self.delete_file_with_report(&handle, &path, deadline, report)?
+                }
+                FilterDecision::Descend { included } => {
+                    kept |=
+                        self.delete_filtered(root, &handle, &path, included, deadline, report)?;
+                }
+                FilterDecision::Excluded => {
+                    report.excluded.push(path);
+                    kept = true;
+                }
+                FilterDecision::NotIncluded => kept = true,
+            }
+        }
+
+        if included && !kept && !self.keeps_root(root, directory) {
+            drop(handle);
+            self.delete_directory_with_retry(parent, directory, deadline, report)?;
+        }
+        Ok(kept || !included)
+    }
+
+    /// Delete directory with full retry logic including process killing.
+    fn delete_directory_with_retry(
+        &self,
+        parent: &DirectoryHandle,
+        path: &Path,
+        deadline: Deadline,
+        report: &mut DeleteReport,
+    ) -> Result<(), DeleteError> {
+        let get_processes = |deadline| get_directory_locking_processes(path, deadline);
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            // Delete contents first (unless a link that is not followed). Entries retry on
+            // their own, so a failure here is final.
+            if self.descends_into(path) {
+                self.delete_directory_contents(parent, path, deadline, report)?;
+            }
+            if contains_mount_point(report, path) || self.plan(parent, path, PlannedAction::Delete)
+            {
+                return Ok(());
+            }
+
+            // Try to remove the directory itself
+            match parent.remove(file_name(path)) {
+                Ok(()) => {
+                    report.entries_deleted += 1;
+                    return Ok(());
+                }
+                Err(_) if !entry_exists(path) => return Ok(()),
+                Err(e) if classify(&e).is_retryable() => {
+                    if self.kill_processes_and_log_info(
+                        true,
+                        attempt,
+                        path,
+                        &e,
+                        deadline,
+                        get_processes,
+                        report,
+                    ) {
+                        return Err(DeleteError::after_retries(
+                            path,
+                            e,
+                            attempt - 1,
+                            deadline,
+                            get_processes,
+                        ));
+                    }
                 }
-            } else if path.is_dir() {
-                // Recursively try to delete subdirectory
-                self.delete_directory_with_retry(&path)?;
+                Err(e) => return Err(DeleteError::from_io(path, e)),
             }
         }
+    }
+
+    /// Delete the entries of a directory in parallel on the worker pool. Entries that fail
+    /// are retried on their own, finding and acting on the processes holding them.
+    fn delete_directory_contents(
+        &self,
+        parent: &DirectoryHandle,
+        directory: &Path,
+        deadline: Deadline,
+        report: &mut DeleteReport,
+    ) -> Result<(), DeleteError> {
+        let handle = match self.open_directory(parent, directory) {
+            Ok(handle) => handle,
+            Err(_) if !entry_exists(directory) => return Ok(()),
+            Err(e) => return Err(DeleteError::from_io(directory, e)),
+        };
+        let entries = handle
+            .entries()
+            .map_err(|e| DeleteError::from_io(directory, e))?;
+        let paths: Vec<PathBuf> = entries
+            .iter()
+            .map(|entry| directory.join(&entry.name))
+            .collect();
+
+        self.delete_entries_with_retry(&handle, &paths, deadline, report)
+    }
 
+    /// Delete files and directories in parallel on the worker pool, each with retry logic.
+    fn delete_entries_with_retry(
+        &self,
+        parent: &DirectoryHandle,
+        paths: &[PathBuf],
+        deadline: Deadline,
+        report: &mut DeleteReport,
+    ) -> Result<(), DeleteError> {
+        let results: Vec<Result<DeleteReport, DeleteError>> = self.pool().install(|| {
+            paths
+                .par_iter()
+                .map(|path| {
+                    let mut entry_report = DeleteReport::default();
+                    self.delete_entry_with_retry(parent, path, deadline, &mut entry_report)?;
+                    Ok(entry_report)
+                })
+                .collect()
+        });
+
+        for result in results {
+            report.merge(result?);
+        }
         Ok(())
     }
 
+    fn delete_entry_with_retry(
+        &self,
+        parent: &DirectoryHandle,
+        path: &Path,
+        deadline: Deadline,
+        report: &mut DeleteReport,
+    ) -> Result<(), DeleteError> {
+        let Some(kind) = EntryKind::of(path) else {
+            // Deleted by something else
+            return Ok(());
+        };
+
+        if deadline.is_expired() {
+            return Err(DeleteError::TimedOut {
+                path: path.to_path_buf(),
+                processes: Vec::new(),
+            });
+        }
+
+        if self.skips_mount_point(path) {
+            report.mount_points.insert(path.to_path_buf());
+            Ok(())
+        } else if !kind.is_directory() {
+            self.delete_file_with_report(parent, path, deadline, report)
+        } else if self.descends_into(path) {
+            self.delete_directory_with_retry(parent, path, deadline, report)
+        } else {
+            self.delete_directory_with_report(parent, path, deadline, report)
+        }
+    }
+
+    /// The worker pool for deleting entries in parallel, created on first use.
+    fn pool(&self) -> &ThreadPool {
+        self.pool.get_or_init(|| {
+            ThreadPoolBuilder::new()
+                .num_threads(self.config.jobs)
+                .thread_name(|i| format!("fops-worker-{}", i))
+                .build()
+                .expect("Failed to create worker threads")
+        })
+    }
+
     /// Kill processes and log information about the retry.
-    /// Returns true if we should throw (exceeded retries), false otherwise.
+    /// Returns true if we should throw (exceeded retries or passed the deadline),
+    /// false otherwise.
+    #[allow(clippy::too_many_arguments)]
     fn kill_processes_and_log_info<F>(
         &self,
         is_directory: bool,
         attempt_number: u32,
         path: &Path,
+        error: &io::Error,
+        deadline: Deadline,
         get_processes: F,
+        report: &mut DeleteReport,
     ) -> bool
     where
-        F: FnOnce() -> Vec<ProcessInfo>,
+        F: Fn(Deadline) -> Vec<ProcessInfo>,
     {
         let is_elevated = crate::elevation::is_process_elevated();
         let elevated_msg = if is_elevated {
@@ -203,15 +1097,23 @@ impl FileAndDirectoryDeleter {
             "ForceOps process is not elevated"
         };
 
-        if attempt_number > self.config.max_retries {
+        let policy = self.config.retry_policy.for_error(error);
+        if attempt_number > policy.max_retries {
             info!(
                 "Exceeded retry count of {}. Failed. {}.",
-                self.config.max_retries, elevated_msg
+                policy.max_retries, elevated_msg
             );
             return true;
         }
 
-        let processes = get_processes();
+        if deadline.is_expired() {
+            info!("Timed out. Failed. {}.", elevated_msg);
+            return true;
+        }
+
+        let delay = deadline.limit(policy.delay(attempt_number));
+        let processes = get_processes(deadline);
+        report.retries.insert(path.to_path_buf(), attempt_number);
         let file_or_dir = if is_directory { "directory" } else { "file" };
         let process_plural = if processes.len() == 1 {
             "process"
@@ -219,48 +1121,283 @@ impl FileAndDirectoryDeleter {
             "processes"
         };
 
-        let process_log_string: String = processes
-            .iter()
-            .map(|p| {
-                format!(
-                    "{} - {}",
-                    p.process_id,
-                    p.executable_name.as_deref().unwrap_or("")
-                )
-            })
-            .collect::<Vec<_>>()
-            .join(", ");
+        let process_log_string = format_processes(&processes);
 
         info!(
-            "Could not delete {} \"{}\". Beginning retry {}/{} in {}ms. {}. Found {} {} to try to kill: [{}].",
+            "Could not delete {} \"{}\". Beginning retry {}/{} in {}ms. {}. Found {} {} to try to {}: [{}].",
             file_or_dir,
             path.display(),
             attempt_number,
-            self.config.max_retries,
-            self.config.retry_delay_ms,
+            policy.max_retries,
+            delay.as_millis(),
             elevated_msg,
             processes.len(),
             process_plural,
+            self.config.lock_holder_action.verb(),
             process_log_string
         );
 
-        thread::sleep(Duration::from_millis(self.config.retry_delay_ms));
-        process::kill_processes(&processes);
+        thread::sleep(delay);
+        match self.config.lock_holder_action {
+            LockHolderAction::Kill => self.kill_processes(&processes, report),
+            LockHolderAction::Suspend => self.suspend_processes(path, &processes, report),
+            LockHolderAction::CloseHandles => self.close_handles_or_kill(path, &processes, report),
+        }
 
         false
     }
+
+    /// Close the handles processes hold to the path, killing the processes where that fails.
+    fn close_handles_or_kill(
+        &self,
+        path: &Path,
+        processes: &[ProcessInfo],
+        report: &mut DeleteReport,
+    ) {
+        let results = handles::close_handles_to_path(processes, path);
+        let to_kill: Vec<ProcessInfo> = results
+            .iter()
+            .filter_map(|result| {
+                let p = &result.process_info;
+                match &result.outcome {
+                    Ok(0) => {
+                        info!(
+                            "Found no handles to close in process {}. Killing it instead.",
+                            p.process_id
+                        );
+                        Some(p.clone())
+                    }
+                    Ok(closed) => {
+                        info!(
+                            "Closed {} {} in process {}.",
+                            closed,
+                            if *closed == 1 { "handle" } else { "handles" },
+                            p.process_id
+                        );
+                        None
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Failed to close handles in process {}: {}. Killing it instead.",
+                            p.process_id, e
+                        );
+                        Some(p.clone())
+                    }
+                }
+            })
+            .collect();
+        report.closed_handles.extend(results);
+
+        self.kill_processes(&to_kill, report);
+    }
+
+    /// Suspend processes that are not already suspended by this deleter, so the path can be
+    /// retried. The processes are resumed once the deletion is done. If the path is still locked
+    /// by processes that are all suspended already, close the handles they hold to it when the
+    /// config allows, as suspending them is not enough.
+    fn suspend_processes(&self, path: &Path, processes: &[ProcessInfo], report: &mut DeleteReport) {
+        let mut suspended = self.suspended_processes.lock().unwrap();
+        let is_suspended = |suspended: &[SuspendedProcess], p: &ProcessInfo| {
+            suspended
+                .iter()
+                .any(|s| s.process_info().process_id == p.process_id)
+        };
+        let not_yet_suspended: Vec<ProcessInfo> = processes
+            .iter()
+            .filter(|p| !is_suspended(&suspended, p))
+            .cloned()
+            .collect();
+        if !not_yet_suspended.is_empty() {
+            let (newly_suspended, results) = suspend::suspend_processes(&not_yet_suspended);
+            report.suspended_processes.extend(results);
+            if !newly_suspended.is_empty() {
+                suspended.extend(newly_suspended);
+                return;
+            }
+        }
+
+        let still_locking: Vec<ProcessInfo> = processes
+            .iter()
+            .filter(|p| is_suspended(&suspended, p))
+            .cloned()
+            .collect();
+        if still_locking.is_empty() {
+            return;
+        }
+        if !self.config.close_handles_when_suspended {
+            info!(
+                "\"{}\" is still locked by suspended processes, whose handles to it are left open.",
+                path.display()
+            );
+            return;
+        }
+
+        let results = handles::close_handles_to_path(&still_locking, path);
+        for result in &results {
+            match &result.outcome {
+                Ok(closed) => info!(
+                    "Closed {} {} in suspended process {}.",
+                    closed,
+                    if *closed == 1 { "handle" } else { "handles" },
+                    result.process_info.process_id
+                ),
+                Err(e) => warn!(
+                    "Failed to close handles in suspended process {}: {}",
+                    result.process_info.process_id, e
+                ),
+            }
+        }
+        report.closed_handles.extend(results);
+    }
+
+    /// Kill processes according to the config, recording them first if they should be restarted.
+    fn kill_processes(&self, processes: &[ProcessInfo], report: &mut DeleteReport) {
+        let launch_infos: Vec<LaunchInfo> = if self.config.restart_killed {
+            processes
+                .iter()
+                .filter_map(|p| {
+                    let launch_info = restart::capture_launch_info(p);
+                    if launch_info.is_none() {
+                        warn!(
+                            "Process {} will not be restarted: failed to read its command line",
+                            p.process_id
+                        );
+                    }
+                    launch_info
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        // Only the locking processes are restarted when killing process trees,
+        // as they are responsible for starting their own children again.
+        let killed = if self.config.kill_process_tree {
+            process::kill_process_trees(processes)
+        } else {
+            process::kill_processes(processes)
+        };
+
+        if !launch_infos.is_empty() {
+            self.killed_processes
+                .lock()
+                .unwrap()
+                .extend(launch_infos.into_iter().filter(|l| {
+                    killed.iter().any(|k| {
+                        k.outcome == KillOutcome::Killed
+                            && k.process_info.process_id == l.process_id
+                    })
+                }));
+        }
+
+        report.killed_processes.extend(killed);
+    }
+}
+
+/// Whether a failed file delete is worth retrying after acting on the processes holding it.
+/// Deleting a running executable fails with access denied, so that is retried as well.
+fn should_retry_file(error: &io::Error) -> bool {
+    classify(error) != ErrorClass::Fatal
+}
+
+/// Open the parent directory of a target, to delete the target through it.
+fn open_parent(path: &Path) -> Result<DirectoryHandle, DeleteError> {
+    DirectoryHandle::open_parent(path).map_err(|e| DeleteError::from_io(path, e))
+}
+
+/// Whether a mount point inside a directory was kept, so the directory must be kept as well.
+fn contains_mount_point(report: &DeleteReport, directory: &Path) -> bool {
+    report
+        .mount_points
+        .iter()
+        .any(|mount_point| mount_point.starts_with(directory))
+}
+
+/// Count the entries in a file or directory and the total size of its files.
+fn measure_entry(path: &Path) -> (u64, u64) {
+    if EntryKind::of(path) == Some(EntryKind::Directory) {
+        measure_directory(path)
+    } else {
+        (1, fs::symlink_metadata(path).map(|m| m.len()).unwrap_or(0))
+    }
+}
+
+/// Count the entries in a directory, including itself, and the total size of its files.
+/// Symlinks are counted as entries, without following them.
+fn measure_directory(directory: &Path) -> (u64, u64) {
+    let mut entries = 1;
+    let mut bytes = 0;
+
+    if let Ok(read_dir) = fs::read_dir(directory) {
+        for entry in read_dir.flatten() {
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => {
+                    let (sub_entries, sub_bytes) = measure_directory(&entry.path());
+                    entries += sub_entries;
+                    bytes += sub_bytes;
+                }
+                _ => {
+                    entries += 1;
+                    bytes += entry.metadata().map(|m| m.len()).unwrap_or(0);
+                }
+            }
+        }
+    }
+
+    (entries, bytes)
 }
 
-fn is_io_or_permission_error(error: &std::io::Error) -> bool {
-    matches!(
-        error.kind(),
-        std::io::ErrorKind::PermissionDenied | std::io::ErrorKind::WouldBlock
-    ) || is_io_error(error)
+fn get_file_locking_processes(path: &Path) -> Vec<ProcessInfo> {
+    match lock_checker::get_locking_processes(&[path]) {
+        Ok(procs) => procs,
+        Err(LockCheckError::GetList { code: 5, message }) => {
+            warn!(
+                "Ignored exception: Failed to get entries (retry 0). (RmGetList() error 5: {})",
+                message
+            );
+            Vec::new()
+        }
+        Err(_) => Vec::new(),
+    }
+}
+
+fn get_directory_locking_processes(path: &Path, deadline: Deadline) -> Vec<ProcessInfo> {
+    lock_checker::get_locking_processes_low_level_until(path, deadline).unwrap_or_default()
 }
 
-fn is_io_error(error: &std::io::Error) -> bool {
-    // On Windows, "The process cannot access the file" is a sharing violation
-    let raw_os_error = error.raw_os_error();
-    matches!(raw_os_error, Some(32) | Some(33)) // ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION
-        || matches!(error.kind(), std::io::ErrorKind::Other)
+/// Get the processes using a directory as their working directory or holding any file in it.
+/// A directory is moved as a whole, so any of them can make the move fail.
+fn get_tree_locking_processes(path: &Path, deadline: Deadline) -> Vec<ProcessInfo> {
+    let mut processes = get_directory_locking_processes(path, deadline);
+
+    let mut files = Vec::new();
+    collect_files(path, deadline, &mut files);
+    let file_refs: Vec<&Path> = files.iter().map(PathBuf::as_path).collect();
+    for process_info in lock_checker::get_locking_processes(&file_refs).unwrap_or_default() {
+        if !processes
+            .iter()
+            .any(|p| p.process_id == process_info.process_id)
+        {
+            processes.push(process_info);
+        }
+    }
+
+    processes
+}
+
+/// Collect the files in a directory tree, stopping once the deadline has passed.
+fn collect_files(directory: &Path, deadline: Deadline, files: &mut Vec<PathBuf>) {
+    if deadline.is_expired() {
+        return;
+    }
+    if let Ok(read_dir) = fs::read_dir(directory) {
+        for entry in read_dir.flatten() {
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => {
+                    collect_files(&entry.path(), deadline, files)
+                }
+                _ => files.push(entry.path()),
+            }
+        }
+    }
 }
@@ -0,0 +1,334 @@
+//! Deleting relative to open directory handles, like `openat` and `unlinkat` with `O_NOFOLLOW`
+//!
+//! Entries are opened by name inside a directory that is already open, without following
+//! symlinks or junctions, so a directory swapped for a link while deleting cannot redirect the
+//! deletion outside the tree.
+
+use crate::utils::EntryKind;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io;
+use std::mem::{offset_of, size_of};
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::os::windows::fs::OpenOptionsExt;
+use std::os::windows::io::{AsRawHandle, FromRawHandle};
+use std::path::Path;
+use windows::Wdk::Foundation::OBJECT_ATTRIBUTES;
+use windows::Wdk::Storage::FileSystem::{
+    FILE_DIRECTORY_FILE, FILE_NON_DIRECTORY_FILE, FILE_OPEN, FILE_OPEN_FOR_BACKUP_INTENT,
+    FILE_OPEN_REPARSE_POINT, FILE_SYNCHRONOUS_IO_NONALERT, NTCREATEFILE_CREATE_OPTIONS,
+    NtCreateFile,
+};
+use windows::Win32::Foundation::{
+    ERROR_INVALID_FUNCTION, ERROR_INVALID_PARAMETER, ERROR_NO_MORE_FILES, ERROR_NOT_SUPPORTED,
+    HANDLE, OBJ_CASE_INSENSITIVE, RtlNtStatusToDosError, UNICODE_STRING,
+};
+use windows::Win32::Storage::FileSystem::{
+    DELETE, FILE_ACCESS_RIGHTS, FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_REPARSE_POINT,
+    FILE_DISPOSITION_FLAG_DELETE, FILE_DISPOSITION_FLAG_IGNORE_READONLY_ATTRIBUTE,
+    FILE_DISPOSITION_FLAG_POSIX_SEMANTICS, FILE_DISPOSITION_INFO, FILE_DISPOSITION_INFO_EX,
+    FILE_DISPOSITION_INFO_EX_FLAGS, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAGS_AND_ATTRIBUTES,
+    FILE_FULL_DIR_INFO, FILE_GENERIC_WRITE, FILE_LIST_DIRECTORY, FILE_READ_ATTRIBUTES,
+    FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, FILE_WRITE_ATTRIBUTES,
+    FileDispositionInfo, FileDispositionInfoEx, FileFullDirectoryInfo,
+    FileFullDirectoryRestartInfo, GetFileInformationByHandleEx, SYNCHRONIZE,
+    SetFileInformationByHandle,
+};
+use windows::Win32::System::IO::IO_STATUS_BLOCK;
+
+/// Reparse points with this bit in their tag stand in for another name, like symlinks and
+/// junctions. Others, like cloud placeholders, are files or directories of their own.
+const NAME_SURROGATE_BIT: u32 = 0x2000_0000;
+
+/// An open directory, whose entries are opened and removed by name
+#[derive(Debug)]
+pub(crate) struct DirectoryHandle(File);
+
+/// An entry of a directory, as listed through its handle
+#[derive(Debug)]
+pub(crate) struct DirectoryEntry {
+    pub name: OsString,
+    pub kind: EntryKind,
+    pub size: u64,
+}
+
+impl DirectoryHandle {
+    /// Open the parent of a target by path, to open and remove the target through it. Entries
+    /// can be opened relative to a directory that cannot be listed, so it is not listed.
+    pub fn open_parent(path: &Path) -> io::Result<Self> {
+        let (Some(parent), Some(_)) = (path.parent(), path.file_name()) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{}' has no parent directory", path.display()),
+            ));
+        };
+        let parent = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+        let file = fs::OpenOptions::new()
+            .access_mode((FILE_READ_ATTRIBUTES | SYNCHRONIZE).0)
+            .share_mode((FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE).0)
+            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS.0)
+            .open(parent)?;
+        Self::from_file(file)
+    }
+
+    /// Open a directory inside this one by name. Unless following links, a symlink or junction
+    /// is refused.
+    pub fn open_directory(&self, name: &OsStr, follow_links: bool) -> io::Result<Self> {
+        let mut options = FILE_DIRECTORY_FILE;
+        if !follow_links {
+            options |= FILE_OPEN_REPARSE_POINT;
+        }
+        let file = self.open_child(
+            name,
+            FILE_LIST_DIRECTORY | FILE_READ_ATTRIBUTES | SYNCHRONIZE,
+            options,
+        )?;
+        Self::from_file(file)
+    }
+
+    /// List the entries of the directory.
+    pub fn entries(&self) -> io::Result<Vec<DirectoryEntry>> {
+        // FILE_FULL_DIR_INFO needs 8 byte alignment
+        let mut buffer = vec![0u64; 8 * 1024];
+        let mut entries = Vec::new();
+        let mut class = FileFullDirectoryRestartInfo;
+
+        loop {
+            let result = unsafe {
+                GetFileInformationByHandleEx(
+                    self.raw_handle(),
+                    class,
+                    buffer.as_mut_ptr().cast(),
+                    (buffer.len() * size_of::<u64>()) as u32,
+                )
+            }
+            .map_err(|_| io::Error::last_os_error());
+            match result {
+                Ok(()) => {}
+                Err(e) if e.raw_os_error() == Some(ERROR_NO_MORE_FILES.0 as i32) => {
+                    return Ok(entries);
+                }
+                Err(e) => return Err(e),
+            }
+            class = FileFullDirectoryInfo;
+
+            let base = buffer.as_ptr().cast::<u8>();
+            let mut offset = 0;
+            loop {
+                let (entry, next_offset) = unsafe {
+                    let info = base.add(offset).cast::<FILE_FULL_DIR_INFO>();
+                    let name = std::slice::from_raw_parts(
+                        base.add(offset + offset_of!(FILE_FULL_DIR_INFO, FileName))
+                            .cast::<u16>(),
+                        (*info).FileNameLength as usize / 2,
+                    );
+                    let entry = DirectoryEntry {
+                        name: OsString::from_wide(name),
+                        // The reparse tag is returned in place of the extended attributes size
+                        kind: entry_kind((*info).FileAttributes, (*info).EaSize),
+                        size: (*info).EndOfFile as u64,
+                    };
+                    (entry, (*info).NextEntryOffset as usize)
+                };
+
+                if entry.name != "." && entry.name != ".." {
+                    entries.push(entry);
+                }
+                if next_offset == 0 {
+                    break;
+                }
+                offset += next_offset;
+            }
+        }
+    }
+
+    /// Remove a file, link or empty directory inside this one by name. A link is removed
+    /// itself, never what it points to.
+    pub fn remove(&self, name: &OsStr) -> io::Result<()> {
+        let file = self.open_child(
+            name,
+            DELETE | FILE_READ_ATTRIBUTES | SYNCHRONIZE,
+            FILE_OPEN_REPARSE_POINT,
+        )?;
+        let handle = HANDLE(file.as_raw_handle());
+
+        // Free the name right away, even while other processes still have the entry open
+        let info = FILE_DISPOSITION_INFO_EX {
+            Flags: FILE_DISPOSITION_INFO_EX_FLAGS(
+                FILE_DISPOSITION_FLAG_DELETE.0
+                    | FILE_DISPOSITION_FLAG_POSIX_SEMANTICS.0
+                    | FILE_DISPOSITION_FLAG_IGNORE_READONLY_ATTRIBUTE.0,
+            ),
+        };
+        let result = unsafe {
+            SetFileInformationByHandle(
+                handle,
+                FileDispositionInfoEx,
+                (&raw const info).cast(),
+                size_of::<FILE_DISPOSITION_INFO_EX>() as u32,
+            )
+        }
+        .map_err(|_| io::Error::last_os_error());
+        match result {
+            Err(e)
+                if [
+                    ERROR_NOT_SUPPORTED,
+                    ERROR_INVALID_FUNCTION,
+                    ERROR_INVALID_PARAMETER,
+                ]
+                .iter()
+                .any(|code| e.raw_os_error() == Some(code.0 as i32)) => {}
+            result => return result,
+        }
+
+        // Older versions of Windows and file systems like FAT only delete the usual way,
+        // which the read-only attribute prevents
+        if file.metadata()?.permissions().readonly() {
+            self.clear_readonly(name)?;
+        }
+        let info = FILE_DISPOSITION_INFO { DeleteFile: true };
+        unsafe {
+            SetFileInformationByHandle(
+                handle,
+                FileDispositionInfo,
+                (&raw const info).cast(),
+                size_of::<FILE_DISPOSITION_INFO>() as u32,
+            )
+        }
+        .map_err(|_| io::Error::last_os_error())
+    }
+
+    /// Check whether a file, link or directory inside this one could be removed right now,
+    /// without removing it. This fails with a sharing violation if another process holds it
+    /// open without sharing delete access.
+    pub fn probe_remove(&self, name: &OsStr) -> io::Result<()> {
+        self.open_child(
+            name,
+            DELETE | FILE_READ_ATTRIBUTES | SYNCHRONIZE,
+            FILE_OPEN_REPARSE_POINT,
+        )
+        .map(drop)
+    }
+
+    /// Open a file inside this one by name to overwrite its contents, clearing its read-only
+    /// attribute first. A link is opened itself, never what it points to, so `None` is returned
+    /// for it.
+    pub fn open_file_for_overwrite(&self, name: &OsStr) -> io::Result<Option<File>> {
+        self.clear_readonly(name)?;
+        let file = self.open_child(
+            name,
+            FILE_GENERIC_WRITE | FILE_READ_ATTRIBUTES | SYNCHRONIZE,
+            FILE_NON_DIRECTORY_FILE | FILE_OPEN_REPARSE_POINT,
+        )?;
+        if file.metadata()?.file_type().is_symlink() {
+            return Ok(None);
+        }
+        Ok(Some(file))
+    }
+
+    /// Clear the read-only attribute of an entry inside this one, or of a link itself.
+    fn clear_readonly(&self, name: &OsStr) -> io::Result<()> {
+        let file = self.open_child(
+            name,
+            FILE_READ_ATTRIBUTES | FILE_WRITE_ATTRIBUTES | SYNCHRONIZE,
+            FILE_OPEN_REPARSE_POINT,
+        )?;
+        let mut permissions = file.metadata()?.permissions();
+        if permissions.readonly() {
+            #[allow(clippy::permissions_set_readonly_false)]
+            permissions.set_readonly(false);
+            file.set_permissions(permissions)?;
+        }
+        Ok(())
+    }
+
+    fn from_file(file: File) -> io::Result<Self> {
+        let file_type = file.metadata()?.file_type();
+        if !file_type.is_dir() || file_type.is_symlink() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                "not a directory, or a link that is not followed",
+            ));
+        }
+        Ok(DirectoryHandle(file))
+    }
+
+    fn open_child(
+        &self,
+        name: &OsStr,
+        access: FILE_ACCESS_RIGHTS,
+        options: NTCREATEFILE_CREATE_OPTIONS,
+    ) -> io::Result<File> {
+        // An empty name would open this directory itself, and separators a path beneath it
+        let mut name: Vec<u16> = name.encode_wide().collect();
+        if name.is_empty() || name.iter().any(|&c| c == '\\' as u16 || c == '/' as u16) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidFilename,
+                "not the name of an entry",
+            ));
+        }
+        let length = u16::try_from(name.len() * 2)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidFilename, "name too long"))?;
+        let object_name = UNICODE_STRING {
+            Length: length,
+            MaximumLength: length,
+            Buffer: windows::core::PWSTR(name.as_mut_ptr()),
+        };
+        let attributes = OBJECT_ATTRIBUTES {
+            Length: size_of::<OBJECT_ATTRIBUTES>() as u32,
+            RootDirectory: self.raw_handle(),
+            ObjectName: &object_name,
+            Attributes: OBJ_CASE_INSENSITIVE,
+            ..Default::default()
+        };
+
+        let mut handle = HANDLE::default();
+        let mut io_status = IO_STATUS_BLOCK::default();
+        let status = unsafe {
+            NtCreateFile(
+                &mut handle,
+                access,
+                &attributes,
+                &mut io_status,
+                None,
+                FILE_FLAGS_AND_ATTRIBUTES(0),
+                FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+                FILE_OPEN,
+                options | FILE_SYNCHRONOUS_IO_NONALERT | FILE_OPEN_FOR_BACKUP_INTENT,
+                None,
+                0,
+            )
+        };
+        if status.0 < 0 {
+            let code = unsafe { RtlNtStatusToDosError(status) };
+            return Err(io::Error::from_raw_os_error(code as i32));
+        }
+        Ok(unsafe { File::from_raw_handle(handle.0) })
+    }
+
+    fn raw_handle(&self) -> HANDLE {
+        HANDLE(self.0.as_raw_handle())
+    }
+}
+
+/// The name of an entry inside its parent directory, empty for paths without one, like the root
+/// of a drive, which no entry is opened by.
+pub(crate) fn file_name(path: &Path) -> &OsStr {
+    path.file_name().unwrap_or_default()
+}
+
+fn entry_kind(attributes: u32, reparse_tag: u32) -> EntryKind {
+    let is_directory = attributes & FILE_ATTRIBUTE_DIRECTORY.0 != 0;
+    let is_link =
+        attributes & FILE_ATTRIBUTE_REPARSE_POINT.0 != 0 && reparse_tag & NAME_SURROGATE_BIT != 0;
+    match (is_directory, is_link) {
+        (true, true) => EntryKind::DirectoryLink,
+        (true, false) => EntryKind::Directory,
+        (false, true) => EntryKind::FileLink,
+        (false, false) => EntryKind::File,
+    }
+}
@@ -3,13 +3,23 @@
 //! Provides functionality to check if the current process is elevated (running as admin)
 //! and to relaunch the process with elevated privileges.
 
+use crate::deleter::{DeleteError, DeleteFailures};
+use crate::handles::CloseHandlesResult;
+use crate::lock_checker::ProcessInfo;
+use crate::process::{KillOutcome, KillResult};
+use crate::report::DeleteReport;
+use crate::restart::LaunchInfo;
+use crate::suspend::{SuspendOutcome, SuspendResult};
 use anyhow::{Result, anyhow};
 use std::ffi::OsStr;
-use std::io::{BufRead, BufReader};
+use std::fs;
+use std::io::{self, BufRead, BufReader};
 use std::os::windows::ffi::OsStrExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::ptr;
-use tracing::info;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{info, warn};
 use windows::Win32::Foundation::{CloseHandle, HANDLE, WAIT_OBJECT_0};
 use windows::Win32::Security::{GetTokenInformation, TOKEN_ELEVATION, TOKEN_QUERY, TokenElevation};
 use windows::Win32::System::Threading::{
@@ -18,6 +28,289 @@ use windows::Win32::System::Threading::{
 use windows::Win32::UI::Shell::{SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW, ShellExecuteExW};
 use windows::core::PCWSTR;
 
+/// The elevated relaunch of fops exited with a failure
+#[derive(Error, Debug)]
+#[error("Child process failed with exit code {exit_code}")]
+pub struct ElevatedProcessFailed {
+    pub exit_code: u32,
+}
+
+/// What the elevated relaunch of fops hands back to the process that launched it, through the
+/// file given with `--elevated-result`
+#[derive(Debug, Default)]
+pub struct ElevatedResult {
+    /// Processes killed by the elevated process, for the launching process to restart, so they
+    /// do not come back elevated.
+    pub killed_processes: Vec<LaunchInfo>,
+    /// What the elevated process deleted, for the launching process to report.
+    pub report: DeleteReport,
+}
+
+impl ElevatedResult {
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let report = &self.report;
+        let mut contents = format!(
+            "deleted\t{}\t{}\t{}\t{}\n",
+            report.entries_deleted,
+            report.bytes_freed,
+            report.elapsed.as_millis(),
+            u8::from(report.elevated)
+        );
+        for (path, retries) in &report.retries {
+            contents.push_str(&format!("retries\t{}\t{}\n", retries, escape_path(path)));
+        }
+        for kill_result in &report.killed_processes {
+            let (outcome, message) = match &kill_result.outcome {
+                KillOutcome::Killed => ("killed", ""),
+                KillOutcome::Skipped => ("skipped", ""),
+                KillOutcome::Failed(message) => ("failed", message.as_str()),
+            };
+            contents.push_str(&format!(
+                "kill\t{}\t{}\t{}\n",
+                process_fields(&kill_result.process_info),
+                outcome,
+                escape(message)
+            ));
+        }
+        for suspend_result in &report.suspended_processes {
+            let (outcome, message) = match &suspend_result.outcome {
+                SuspendOutcome::Suspended => ("suspended", ""),
+                SuspendOutcome::Skipped => ("skipped", ""),
+                SuspendOutcome::Failed(message) => ("failed", message.as_str()),
+            };
+            contents.push_str(&format!(
+                "suspend\t{}\t{}\t{}\n",
+                process_fields(&suspend_result.process_info),
+                outcome,
+                escape(message)
+            ));
+        }
+        for close_result in &report.closed_handles {
+            let (outcome, message) = match &close_result.outcome {
+                Ok(closed) => ("closed", closed.to_string()),
+                Err(message) => ("failed", escape(message)),
+            };
+            contents.push_str(&format!(
+                "closed\t{}\t{}\t{}\n",
+                process_fields(&close_result.process_info),
+                outcome,
+                message
+            ));
+        }
+        for path in &report.excluded {
+            contents.push_str(&format!("excluded\t{}\n", escape_path(path)));
+        }
+        for (path, id) in &report.quarantined {
+            contents.push_str(&format!(
+                "quarantined\t{}\t{}\n",
+                escape(id),
+                escape_path(path)
+            ));
+        }
+        for path in &report.tombstones {
+            contents.push_str(&format!("tombstone\t{}\n", escape_path(path)));
+        }
+        for path in &report.mount_points {
+            contents.push_str(&format!("mount_point\t{}\n", escape_path(path)));
+        }
+        for launch_info in &self.killed_processes {
+            let environment = launch_info
+                .environment
+                .as_deref()
+                .unwrap_or_default()
+                .iter()
+                .map(|c| format!("{:04x}", c))
+                .collect::<String>();
+            contents.push_str(&format!(
+                "killed\t{}\t{}\t{}\t{}\t{}\n",
+                launch_info.process_id,
+                escape(&launch_info.executable),
+                escape(&launch_info.command_line),
+                escape(launch_info.current_directory.as_deref().unwrap_or_default()),
+                environment
+            ));
+        }
+        fs::write(path, contents)
+    }
+
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let invalid = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected line '{}'", line),
+            )
+        };
+
+        let mut result = Self::default();
+        let report = &mut result.report;
+        for line in fs::read_to_string(path)?.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields.as_slice() {
+                ["deleted", entries, bytes, elapsed_ms, elevated] => {
+                    report.entries_deleted = entries.parse().map_err(|_| invalid(line))?;
+                    report.bytes_freed = bytes.parse().map_err(|_| invalid(line))?;
+                    report.elapsed =
+                        Duration::from_millis(elapsed_ms.parse().map_err(|_| invalid(line))?);
+                    report.elevated = *elevated == "1";
+                }
+                ["retries", retries, path] => {
+                    report.retries.insert(
+                        unescape_path(path),
+                        retries.parse().map_err(|_| invalid(line))?,
+                    );
+                }
+                [
+                    "kill",
+                    pid,
+                    start_time,
+                    executable_name,
+                    application_name,
+                    outcome,
+                    message,
+                ] => {
+                    let outcome = match *outcome {
+                        "killed" => KillOutcome::Killed,
+                        "skipped" => KillOutcome::Skipped,
+                        "failed" => KillOutcome::Failed(unescape(message)),
+                        _ => return Err(invalid(line)),
+                    };
+                    report.killed_processes.push(KillResult {
+                        process_info: parse_process_fields(
+                            pid,
+                            start_time,
+                            executable_name,
+                            application_name,
+                        )
+                        .ok_or_else(|| invalid(line))?,
+                        outcome,
+                    });
+                }
+                [
+                    "suspend",
+                    pid,
+                    start_time,
+                    executable_name,
+                    application_name,
+                    outcome,
+                    message,
+                ] => {
+                    let outcome = match *outcome {
+                        "suspended" => SuspendOutcome::Suspended,
+                        "skipped" => SuspendOutcome::Skipped,
+                        "failed" => SuspendOutcome::Failed(unescape(message)),
+                        _ => return Err(invalid(line)),
+                    };
+                    report.suspended_processes.push(SuspendResult {
+                        process_info: parse_process_fields(
+                            pid,
+                            start_time,
+                            executable_name,
+                            application_name,
+                        )
+                        .ok_or_else(|| invalid(line))?,
+                        outcome,
+                    });
+                }
+                [
+                    "closed",
+                    pid,
+                    start_time,
+                    executable_name,
+                    application_name,
+                    outcome,
+                    message,
+                ] => {
+                    let outcome = match *outcome {
+                        "closed" => Ok(message.parse().map_err(|_| invalid(line))?),
+                        "failed" => Err(unescape(message)),
+                        _ => return Err(invalid(line)),
+                    };
+                    report.closed_handles.push(CloseHandlesResult {
+                        process_info: parse_process_fields(
+                            pid,
+                            start_time,
+                            executable_name,
+                            application_name,
+                        )
+                        .ok_or_else(|| invalid(line))?,
+                        outcome,
+                    });
+                }
+                ["excluded", path] => report.excluded.push(unescape_path(path)),
+                ["quarantined", id, path] => {
+                    report.quarantined.insert(unescape_path(path), unescape(id));
+                }
+                ["tombstone", path] => report.tombstones.push(unescape_path(path)),
+                ["mount_point", path] => {
+                    report.mount_points.insert(unescape_path(path));
+                }
+                [
+                    "killed",
+                    pid,
+                    executable,
+                    command_line,
+                    current_directory,
+                    environment,
+                ] => {
+                    let environment = (0..environment.len())
+                        .step_by(4)
+                        .map(|i| {
+                            environment
+                                .get(i..i + 4)
+                                .and_then(|c| u16::from_str_radix(c, 16).ok())
+                        })
+                        .collect::<Option<Vec<u16>>>()
+                        .ok_or_else(|| invalid(line))?;
+                    result.killed_processes.push(LaunchInfo {
+                        process_id: pid.parse().map_err(|_| invalid(line))?,
+                        executable: unescape(executable),
+                        command_line: unescape(command_line),
+                        current_directory: Some(unescape(current_directory))
+                            .filter(|d| !d.is_empty()),
+                        environment: Some(environment).filter(|e| !e.is_empty()),
+                    });
+                }
+                _ => return Err(invalid(line)),
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Formats a process as the tab-separated process ID, start time, executable name and
+/// application name.
+fn process_fields(process_info: &ProcessInfo) -> String {
+    format!(
+        "{}\t{}\t{}\t{}",
+        process_info.process_id,
+        process_info
+            .start_time
+            .map(|t| t.to_string())
+            .unwrap_or_default(),
+        escape(process_info.executable_name.as_deref().unwrap_or_default()),
+        escape(process_info.application_name.as_deref().unwrap_or_default())
+    )
+}
+
+/// Parses the fields written by `process_fields`.
+fn parse_process_fields(
+    pid: &str,
+    start_time: &str,
+    executable_name: &str,
+    application_name: &str,
+) -> Option<ProcessInfo> {
+    let start_time = match start_time {
+        "" => None,
+        start_time => Some(start_time.parse().ok()?),
+    };
+    Some(ProcessInfo {
+        process_id: pid.parse().ok()?,
+        start_time,
+        executable_name: Some(unescape(executable_name)).filter(|n| !n.is_empty()),
+        application_name: Some(unescape(application_name)).filter(|n| !n.is_empty()),
+    })
+}
+
 /// Checks if the current process is running with elevated (administrator) privileges.
 pub fn is_process_elevated() -> bool {
     unsafe {
@@ -45,17 +338,22 @@ pub fn is_process_elevated() -> bool {
 }
 
 /// Runs an action and relaunches as elevated if it fails with a permission error.
-pub fn run_with_relaunch_as_elevated<F, A>(action: F, build_args: A) -> Result<()>
+/// `on_result` gets what the elevated process handed back, even when it failed.
+pub fn run_with_relaunch_as_elevated<F, A, R>(action: F, build_args: A, on_result: R) -> Result<()>
 where
     F: FnOnce() -> Result<()>,
     A: FnOnce() -> Vec<String>,
+    R: FnOnce(ElevatedResult),
 {
     match action() {
         Ok(()) => Ok(()),
         Err(e) if is_permission_error(&e) && !is_process_elevated() => {
-            let args = build_args();
+            let mut args = build_args();
             let output_file =
                 std::env::temp_dir().join(format!("forceops_{}.tmp", std::process::id()));
+            let result_file =
+                std::env::temp_dir().join(format!("forceops_{}.result", std::process::id()));
+            args.push(format!("--elevated-result={}", result_file.display()));
 
             info!(
                 "Unable to perform operation as an unelevated process. Retrying as elevated and logging to \"{}\".",
@@ -63,6 +361,11 @@ where
             );
 
             let exit_code = relaunch_as_elevated(&args, &output_file)?;
+            match ElevatedResult::read(&result_file) {
+                Ok(result) => on_result(result),
+                Err(e) => warn!("Failed to read the result of the elevated process: {}", e),
+            }
+            let _ = std::fs::remove_file(&result_file);
 
             if exit_code != 0 {
                 // Read and display the output from the elevated process
@@ -73,7 +376,7 @@ where
                     }
                 }
                 let _ = std::fs::remove_file(&output_file);
-                Err(anyhow!("Child process failed with exit code {}", exit_code))
+                Err(ElevatedProcessFailed { exit_code }.into())
             } else {
                 info!("Successfully deleted as admin");
                 let _ = std::fs::remove_file(&output_file);
@@ -85,10 +388,48 @@ where
 }
 
 fn is_permission_error(error: &anyhow::Error) -> bool {
-    let err_string = error.to_string().to_lowercase();
-    err_string.contains("access")
-        || err_string.contains("permission")
-        || err_string.contains("denied")
+    error
+        .downcast_ref::<DeleteError>()
+        .is_some_and(DeleteError::needs_elevation)
+        || error
+            .downcast_ref::<DeleteFailures>()
+            .is_some_and(DeleteFailures::needs_elevation)
+}
+
+/// Escape a field of the result file, which is tab separated with one entry per line.
+fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn escape_path(path: &Path) -> String {
+    escape(&path.to_string_lossy())
+}
+
+fn unescape_path(field: &str) -> PathBuf {
+    PathBuf::from(unescape(field))
+}
+
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
 }
 
 /// Relaunches the current executable with elevated privileges.
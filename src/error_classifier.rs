@@ -0,0 +1,72 @@
+//! Classifies I/O errors from delete operations to decide whether to retry them.
+
+use std::io;
+
+/// Why a delete operation failed, as far as retrying it is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The entry is held open or in use by a process. Retrying after acting on the
+    /// processes holding it may succeed.
+    Locked,
+    /// Something else changed the entry concurrently, e.g. created a file in a directory
+    /// being removed or deleted the entry first. Retrying may succeed.
+    Racing,
+    /// The current process lacks the rights to delete the entry. Retrying as an elevated
+    /// process may succeed.
+    NeedsElevation,
+    /// Retrying will not help.
+    Fatal,
+}
+
+impl ErrorClass {
+    /// Whether the operation may succeed when retried by the same process.
+    pub fn is_retryable(self) -> bool {
+        matches!(self, ErrorClass::Locked | ErrorClass::Racing)
+    }
+}
+
+/// Classify an error returned when deleting a file or directory.
+pub fn classify(error: &io::Error) -> ErrorClass {
+    if let Some(code) = error.raw_os_error()
+        && let Some(class) = classify_os_error(code)
+    {
+        return class;
+    }
+
+    match error.kind() {
+        io::ErrorKind::ResourceBusy
+        | io::ErrorKind::ExecutableFileBusy
+        | io::ErrorKind::WouldBlock
+        | io::ErrorKind::Other => ErrorClass::Locked,
+        io::ErrorKind::NotFound | io::ErrorKind::DirectoryNotEmpty => ErrorClass::Racing,
+        io::ErrorKind::PermissionDenied => ErrorClass::NeedsElevation,
+        _ => ErrorClass::Fatal,
+    }
+}
+
+fn classify_os_error(code: i32) -> Option<ErrorClass> {
+    const ERROR_FILE_NOT_FOUND: i32 = 2;
+    const ERROR_PATH_NOT_FOUND: i32 = 3;
+    const ERROR_ACCESS_DENIED: i32 = 5;
+    const ERROR_WRITE_PROTECT: i32 = 19;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+    const ERROR_DIR_NOT_EMPTY: i32 = 145;
+    const ERROR_BUSY: i32 = 170;
+    const ERROR_USER_MAPPED_FILE: i32 = 1224;
+    const ERROR_DELETE_PENDING: i32 = 303;
+    const ERROR_PRIVILEGE_NOT_HELD: i32 = 1314;
+
+    match code {
+        ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION | ERROR_BUSY | ERROR_USER_MAPPED_FILE => {
+            Some(ErrorClass::Locked)
+        }
+        // A directory is not empty while a deleted child is still held open, or when a
+        // process created a new entry in it.
+        ERROR_FILE_NOT_FOUND | ERROR_PATH_NOT_FOUND | ERROR_DIR_NOT_EMPTY
+        | ERROR_DELETE_PENDING => Some(ErrorClass::Racing),
+        ERROR_ACCESS_DENIED | ERROR_PRIVILEGE_NOT_HELD => Some(ErrorClass::NeedsElevation),
+        ERROR_WRITE_PROTECT => Some(ErrorClass::Fatal),
+        _ => None,
+    }
+}
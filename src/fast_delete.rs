@@ -0,0 +1,142 @@
+//! The fast path for deleting a directory: a parallel walk that tries each entry once
+
+use crate::dir_handle::{DirectoryHandle, file_name};
+use crate::utils::{EntryKind, can_follow_link, is_mount_point};
+use rayon::prelude::*;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What the fast path deleted, and the entries it could not delete
+#[derive(Debug, Default)]
+pub(crate) struct FastDeleteOutcome {
+    pub entries_deleted: u64,
+    pub bytes_freed: u64,
+    /// Entries that could not be deleted, with the error. Their ancestors are left in place,
+    /// as are directories that could not be read.
+    pub failures: Vec<(PathBuf, io::Error)>,
+    /// Mount points left in place with their contents and ancestors, when not crossing volumes.
+    pub mount_points: Vec<PathBuf>,
+}
+
+impl FastDeleteOutcome {
+    fn merge(&mut self, other: FastDeleteOutcome) {
+        self.entries_deleted += other.entries_deleted;
+        self.bytes_freed += other.bytes_freed;
+        self.failures.extend(other.failures);
+        self.mount_points.extend(other.mount_points);
+    }
+}
+
+/// Delete a directory inside an open parent directory, and everything in it, trying each entry
+/// once without retrying. Entries are opened and removed through the handle of their parent,
+/// never following links on the way unless `follow_links`, and in parallel on the current rayon
+/// pool. With `one_file_system`, mount points inside the directory are left in place. With
+/// `follow_links`, the contents of directories that links point to are deleted before the links.
+pub(crate) fn remove_tree(
+    parent: &DirectoryHandle,
+    directory: &Path,
+    one_file_system: bool,
+    follow_links: bool,
+) -> FastDeleteOutcome {
+    let name = file_name(directory);
+    let mut outcome = match parent.open_directory(name, follow_links) {
+        Ok(handle) => remove_contents(&handle, directory, one_file_system, follow_links),
+        Err(e) => FastDeleteOutcome {
+            failures: vec![(directory.to_path_buf(), e)],
+            ..Default::default()
+        },
+    };
+
+    // Removing the directory would fail while anything is left in it
+    if outcome.failures.is_empty() && outcome.mount_points.is_empty() {
+        outcome.merge(outcome_of_removal(directory, parent.remove(name), 0));
+    }
+
+    outcome
+}
+
+/// Delete everything in an open directory, whose path is only used to report entries.
+fn remove_contents(
+    directory: &DirectoryHandle,
+    path: &Path,
+    one_file_system: bool,
+    follow_links: bool,
+) -> FastDeleteOutcome {
+    let entries = match directory.entries() {
+        Ok(entries) => entries,
+        Err(e) => {
+            return FastDeleteOutcome {
+                failures: vec![(path.to_path_buf(), e)],
+                ..Default::default()
+            };
+        }
+    };
+
+    let outcomes: Vec<FastDeleteOutcome> = entries
+        .par_iter()
+        .map(|entry| {
+            let entry_path = path.join(&entry.name);
+            let descend = match entry.kind {
+                EntryKind::Directory => true,
+                EntryKind::DirectoryLink => follow_links && can_follow_link(&entry_path),
+                EntryKind::File | EntryKind::FileLink => false,
+            };
+
+            if one_file_system && entry.kind.is_directory() && is_mount_point(&entry_path) {
+                return FastDeleteOutcome {
+                    mount_points: vec![entry_path],
+                    ..Default::default()
+                };
+            }
+
+            let mut outcome = FastDeleteOutcome::default();
+            if descend {
+                match directory.open_directory(&entry.name, follow_links) {
+                    Ok(child) => {
+                        outcome =
+                            remove_contents(&child, &entry_path, one_file_system, follow_links);
+                    }
+                    Err(e) => outcome.failures.push((entry_path.clone(), e)),
+                }
+                if !outcome.failures.is_empty() || !outcome.mount_points.is_empty() {
+                    return outcome;
+                }
+            }
+
+            let size = if entry.kind == EntryKind::File {
+                entry.size
+            } else {
+                0
+            };
+            outcome.merge(outcome_of_removal(
+                &entry_path,
+                directory.remove(&entry.name),
+                size,
+            ));
+            outcome
+        })
+        .collect();
+
+    let mut outcome = FastDeleteOutcome::default();
+    for entry_outcome in outcomes {
+        outcome.merge(entry_outcome);
+    }
+    outcome
+}
+
+/// The outcome of removing a single file, link or empty directory.
+fn outcome_of_removal(path: &Path, result: io::Result<()>, size: u64) -> FastDeleteOutcome {
+    match result {
+        Ok(()) => FastDeleteOutcome {
+            entries_deleted: 1,
+            bytes_freed: size,
+            ..Default::default()
+        },
+        // Something else deleted it first
+        Err(e) if e.kind() == io::ErrorKind::NotFound => FastDeleteOutcome::default(),
+        Err(e) => FastDeleteOutcome {
+            failures: vec![(path.to_path_buf(), e)],
+            ..Default::default()
+        },
+    }
+}
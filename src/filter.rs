@@ -0,0 +1,106 @@
+//! Include and exclude globs selecting which entries of a directory to delete
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::path::Path;
+
+/// What to do with an entry inside a directory being deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterDecision {
+    /// Delete the entry, and everything in it if it is a directory.
+    Delete,
+    /// Decide for each entry in the directory, deleting the directory itself
+    /// if it is included and nothing in it was kept.
+    Descend { included: bool },
+    /// Keep the entry, as it matches an exclude glob.
+    Excluded,
+    /// Keep the entry, as it does not match any include glob.
+    NotIncluded,
+}
+
+/// Selects the entries to delete inside a directory, using include and exclude globs.
+///
+/// Globs are matched against the path relative to the directory being deleted and
+/// against the entry's file name, so `.gitkeep` matches at any depth.
+/// An empty filter deletes everything.
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+}
+
+impl PathFilter {
+    /// Create a filter deleting entries that match any include glob, or everything when
+    /// there are none, except for entries that match any exclude glob.
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, globset::Error> {
+        Ok(Self {
+            include: build_glob_set(include)?,
+            exclude: build_glob_set(exclude)?,
+        })
+    }
+
+    /// Whether the filter deletes everything.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_none() && self.exclude.is_none()
+    }
+
+    /// Whether the root of a directory being deleted is included.
+    pub fn includes_root(&self) -> bool {
+        self.include.is_none()
+    }
+
+    /// Decide what to do with an entry, given its path relative to the directory being
+    /// deleted and whether its parent directory is included.
+    pub fn decide(
+        &self,
+        relative_path: &Path,
+        is_dir: bool,
+        parent_included: bool,
+    ) -> FilterDecision {
+        if self
+            .exclude
+            .as_ref()
+            .is_some_and(|g| matches(g, relative_path))
+        {
+            return FilterDecision::Excluded;
+        }
+
+        let included = parent_included
+            || self
+                .include
+                .as_ref()
+                .is_some_and(|g| matches(g, relative_path));
+
+        if is_dir {
+            // Something inside may still be excluded, so only delete a directory
+            // wholesale when nothing can be.
+            if included && self.exclude.is_none() {
+                FilterDecision::Delete
+            } else {
+                FilterDecision::Descend { included }
+            }
+        } else if included {
+            FilterDecision::Delete
+        } else {
+            FilterDecision::NotIncluded
+        }
+    }
+}
+
+fn build_glob_set(globs: &[String]) -> Result<Option<GlobSet>, globset::Error> {
+    if globs.is_empty() {
+        return Ok(None);
+    }
+
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob)?);
+    }
+    builder.build().map(Some)
+}
+
+fn matches(globs: &GlobSet, relative_path: &Path) -> bool {
+    globs.is_match(relative_path)
+        || relative_path
+            .file_name()
+            .is_some_and(|name| globs.is_match(Path::new(name)))
+}
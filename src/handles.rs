@@ -0,0 +1,267 @@
+//! Closing handles inside processes holding a lock
+//!
+//! Instead of killing a process, the handles it holds to the target are closed remotely by
+//! duplicating them with `DUPLICATE_CLOSE_SOURCE`. This is experimental: the process is
+//! not aware that its handles were closed and may fail when it next uses them.
+
+use crate::lock_checker::ProcessInfo;
+use crate::process::process_creation_time_from_handle;
+use std::fs::File;
+use std::os::windows::io::AsRawHandle;
+use std::path::Path;
+use std::sync::OnceLock;
+use windows::Win32::Foundation::{
+    CloseHandle, DUPLICATE_CLOSE_SOURCE, DUPLICATE_SAME_ACCESS, DuplicateHandle, HANDLE, NTSTATUS,
+};
+use windows::Win32::Storage::FileSystem::{
+    FILE_NAME_NORMALIZED, FILE_TYPE_DISK, GetFileType, GetFinalPathNameByHandleW,
+};
+use windows::Win32::System::Threading::{
+    GetCurrentProcess, OpenProcess, PROCESS_DUP_HANDLE, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+
+// Link to ntdll for NtQuerySystemInformation
+#[link(name = "ntdll")]
+unsafe extern "system" {
+    fn NtQuerySystemInformation(
+        system_information_class: u32,
+        system_information: *mut std::ffi::c_void,
+        system_information_length: u32,
+        return_length: *mut u32,
+    ) -> NTSTATUS;
+}
+
+const SYSTEM_EXTENDED_HANDLE_INFORMATION_CLASS: u32 = 64;
+const STATUS_INFO_LENGTH_MISMATCH: NTSTATUS = NTSTATUS(0xC0000004_u32 as i32);
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SystemHandleTableEntryInfoEx {
+    object: *mut std::ffi::c_void,
+    unique_process_id: usize,
+    handle_value: usize,
+    granted_access: u32,
+    creator_back_trace_index: u16,
+    object_type_index: u16,
+    handle_attributes: u32,
+    reserved: u32,
+}
+
+#[repr(C)]
+struct SystemHandleInformationEx {
+    number_of_handles: usize,
+    reserved: usize,
+    handles: [SystemHandleTableEntryInfoEx; 1],
+}
+
+/// A process whose handles to a path fops tried to close
+#[derive(Debug, Clone)]
+pub struct CloseHandlesResult {
+    pub process_info: ProcessInfo,
+    /// The number of handles closed, or why they could not be closed.
+    pub outcome: Result<usize, String>,
+}
+
+/// Closes the handles each process holds to `path`, or to anything inside it if `path` is a
+/// directory, returning the outcome for each process in the same order. The handles open in
+/// the system are looked up once for all of the processes.
+pub fn close_handles_to_path(processes: &[ProcessInfo], path: &Path) -> Vec<CloseHandlesResult> {
+    let lookup = std::fs::canonicalize(path)
+        .map_err(|e| e.to_string())
+        .and_then(|target| Ok((normalize(&target), file_object_type_index()?)))
+        .and_then(|(target, file_type_index)| {
+            Ok((target, file_type_index, query_system_handles()?))
+        });
+    let (target, file_type_index, system_handles) = match lookup {
+        Ok(lookup) => lookup,
+        Err(e) => {
+            return processes
+                .iter()
+                .map(|process_info| CloseHandlesResult {
+                    process_info: process_info.clone(),
+                    outcome: Err(e.clone()),
+                })
+                .collect();
+        }
+    };
+
+    processes
+        .iter()
+        .map(|process_info| {
+            let handles: Vec<usize> = system_handles
+                .iter()
+                .filter(|h| {
+                    h.unique_process_id == process_info.process_id as usize
+                        && h.object_type_index == file_type_index
+                })
+                .map(|h| h.handle_value)
+                .collect();
+            CloseHandlesResult {
+                process_info: process_info.clone(),
+                outcome: close_handles_in_process(process_info, &target, &handles),
+            }
+        })
+        .collect()
+}
+
+/// Closes the file handles of a process, out of `handles`, that are to `target` or to anything
+/// inside it.
+fn close_handles_in_process(
+    process_info: &ProcessInfo,
+    target: &str,
+    handles: &[usize],
+) -> Result<usize, String> {
+    unsafe {
+        let process = OpenProcess(
+            PROCESS_DUP_HANDLE | PROCESS_QUERY_LIMITED_INFORMATION,
+            false,
+            process_info.process_id,
+        )
+        .map_err(|e| e.to_string())?;
+
+        let _guard = HandleGuard(process);
+
+        if let Some(expected_start_time) = process_info.start_time
+            && process_creation_time_from_handle(process) != Some(expected_start_time)
+        {
+            return Err("the process exited and its ID was reused after lock detection".into());
+        }
+
+        let mut closed = 0;
+        for &handle_value in handles {
+            let remote_handle = HANDLE(handle_value as _);
+            let Some(handle_path) = get_remote_handle_path(process, remote_handle) else {
+                continue;
+            };
+
+            if handle_path == target || handle_path.starts_with(&format!("{}\\", target)) {
+                DuplicateHandle(
+                    process,
+                    remote_handle,
+                    HANDLE::default(),
+                    std::ptr::null_mut(),
+                    0,
+                    false,
+                    DUPLICATE_CLOSE_SOURCE,
+                )
+                .map_err(|e| e.to_string())?;
+                closed += 1;
+            }
+        }
+
+        Ok(closed)
+    }
+}
+
+/// Get the path of a file handle owned by another process, normalized for comparison
+unsafe fn get_remote_handle_path(process: HANDLE, remote_handle: HANDLE) -> Option<String> {
+    unsafe {
+        let mut local_handle = HANDLE::default();
+        DuplicateHandle(
+            process,
+            remote_handle,
+            GetCurrentProcess(),
+            &mut local_handle,
+            0,
+            false,
+            DUPLICATE_SAME_ACCESS,
+        )
+        .ok()?;
+
+        let _guard = HandleGuard(local_handle);
+
+        // Only query disk files, querying the name of other handles such as pipes can block
+        if GetFileType(local_handle) != FILE_TYPE_DISK {
+            return None;
+        }
+
+        let mut buffer = vec![0u16; 1024];
+        let mut len = GetFinalPathNameByHandleW(local_handle, &mut buffer, FILE_NAME_NORMALIZED);
+        if len as usize > buffer.len() {
+            buffer.resize(len as usize, 0);
+            len = GetFinalPathNameByHandleW(local_handle, &mut buffer, FILE_NAME_NORMALIZED);
+        }
+        if len == 0 || len as usize > buffer.len() {
+            return None;
+        }
+
+        String::from_utf16(&buffer[..len as usize])
+            .ok()
+            .map(|p| normalize(Path::new(&p)))
+    }
+}
+
+/// Find the object type index of file handles, which differs between Windows versions,
+/// by looking up a file handle owned by this process. It is looked up once per process.
+fn file_object_type_index() -> Result<u16, String> {
+    static FILE_OBJECT_TYPE_INDEX: OnceLock<u16> = OnceLock::new();
+    if let Some(&index) = FILE_OBJECT_TYPE_INDEX.get() {
+        return Ok(index);
+    }
+
+    let file = File::open(std::env::current_exe().map_err(|e| e.to_string())?)
+        .map_err(|e| e.to_string())?;
+    let handle_value = file.as_raw_handle() as usize;
+    let current_pid = std::process::id() as usize;
+
+    query_system_handles()?
+        .into_iter()
+        .find(|h| h.unique_process_id == current_pid && h.handle_value == handle_value)
+        .map(|h| *FILE_OBJECT_TYPE_INDEX.get_or_init(|| h.object_type_index))
+        .ok_or_else(|| "Failed to determine the object type of file handles".to_string())
+}
+
+/// Get every handle open in the system
+fn query_system_handles() -> Result<Vec<SystemHandleTableEntryInfoEx>, String> {
+    // Use usize elements so the buffer is aligned for SystemHandleInformationEx
+    let mut buffer: Vec<usize> = vec![0; 1 << 16];
+
+    loop {
+        let byte_length = buffer.len() * std::mem::size_of::<usize>();
+        let mut return_length: u32 = 0;
+        let status = unsafe {
+            NtQuerySystemInformation(
+                SYSTEM_EXTENDED_HANDLE_INFORMATION_CLASS,
+                buffer.as_mut_ptr() as *mut _,
+                byte_length as u32,
+                &mut return_length,
+            )
+        };
+
+        if status == STATUS_INFO_LENGTH_MISMATCH {
+            // Handles may be opened between calls, so leave some room
+            let needed = (return_length as usize).max(byte_length) * 2;
+            buffer.resize(needed / std::mem::size_of::<usize>(), 0);
+            continue;
+        }
+        status.ok().map_err(|e| e.to_string())?;
+
+        unsafe {
+            let information = buffer.as_ptr() as *const SystemHandleInformationEx;
+            let count = (*information).number_of_handles;
+            let first =
+                std::ptr::addr_of!((*information).handles) as *const SystemHandleTableEntryInfoEx;
+            return Ok(std::slice::from_raw_parts(first, count).to_vec());
+        }
+    }
+}
+
+fn normalize(path: &Path) -> String {
+    let lower = path.to_string_lossy().to_lowercase();
+    lower
+        .strip_prefix(r"\\?\")
+        .unwrap_or(&lower)
+        .trim_end_matches('\\')
+        .to_string()
+}
+
+/// Closes a handle when dropped
+struct HandleGuard(HANDLE);
+
+impl Drop for HandleGuard {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseHandle(self.0);
+        }
+    }
+}
@@ -5,12 +5,30 @@
 
 pub mod cli;
 pub mod config;
+pub mod deadline;
 pub mod deleter;
+mod dir_handle;
 pub mod elevation;
+pub mod error_classifier;
+mod fast_delete;
+pub mod filter;
+pub mod handles;
 pub mod lock_checker;
 pub mod process;
+pub mod quarantine;
+pub mod report;
+pub mod restart;
+pub mod retry;
+pub mod safety;
+pub mod shred;
+pub mod suspend;
+pub mod tombstone;
+pub mod trash;
 pub mod utils;
 
-pub use config::ForceOpsConfig;
-pub use deleter::FileAndDirectoryDeleter;
+pub use config::{ForceOpsConfig, LockHolderAction};
+pub use deadline::Deadline;
+pub use deleter::{DeleteAllOutcome, DeleteError, DeleteFailures, FileAndDirectoryDeleter};
 pub use lock_checker::{ProcessInfo, get_locking_processes, get_locking_processes_low_level};
+pub use report::{DeleteReport, DryRunReport};
+pub use retry::{Backoff, RetryPolicies, RetryPolicy};
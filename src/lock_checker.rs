@@ -6,7 +6,10 @@
 //! - NtQueryInformationFile with FileProcessIdsUsingFileInformation (for files, low-level)
 //! - Process enumeration with PEB reading (for directories)
 
+use crate::deadline::Deadline;
+use crate::process::{filetime_to_ticks, process_creation_time};
 use std::ffi::OsStr;
+use std::fmt;
 use std::os::windows::ffi::OsStrExt;
 use std::path::Path;
 use thiserror::Error;
@@ -43,10 +46,33 @@ pub enum LockCheckError {
 #[derive(Debug, Clone)]
 pub struct ProcessInfo {
     pub process_id: u32,
+    /// Creation time of the process as a FILETIME tick count. Together with the process ID
+    /// this identifies the process, as process IDs are reused once a process exits.
+    pub start_time: Option<u64>,
     pub executable_name: Option<String>,
     pub application_name: Option<String>,
 }
 
+impl fmt::Display for ProcessInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - {}",
+            self.process_id,
+            self.executable_name.as_deref().unwrap_or("")
+        )
+    }
+}
+
+/// Format processes for log and error messages, e.g. "123 - a.exe, 456 - b.exe".
+pub fn format_processes(processes: &[ProcessInfo]) -> String {
+    processes
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 // Link to ntdll for NtQueryInformationProcess
 #[link(name = "ntdll")]
 unsafe extern "system" {
@@ -182,6 +208,7 @@ pub fn get_locking_processes(paths: &[&Path]) -> Result<Vec<ProcessInfo>, LockCh
 
                 ProcessInfo {
                     process_id: info.Process.dwProcessId,
+                    start_time: Some(filetime_to_ticks(info.Process.ProcessStartTime)),
                     executable_name: exe_name.or_else(|| app_name.clone()),
                     application_name: app_name,
                 }
@@ -195,6 +222,15 @@ pub fn get_locking_processes(paths: &[&Path]) -> Result<Vec<ProcessInfo>, LockCh
 /// Get processes whose working directory is within the target directory.
 /// This is used for directory lock detection.
 pub fn get_locking_processes_low_level(path: &Path) -> Result<Vec<ProcessInfo>, LockCheckError> {
+    get_locking_processes_low_level_until(path, Deadline::NONE)
+}
+
+/// Like [`get_locking_processes_low_level`], but stops enumerating processes once the
+/// deadline has passed, returning those found so far.
+pub fn get_locking_processes_low_level_until(
+    path: &Path,
+    deadline: Deadline,
+) -> Result<Vec<ProcessInfo>, LockCheckError> {
     let target_path = std::fs::canonicalize(path).map_err(|_| {
         LockCheckError::FileNotFound(format!("Cannot canonicalize '{}'", path.display()))
     })?;
@@ -225,6 +261,9 @@ pub fn get_locking_processes_low_level(path: &Path) -> Result<Vec<ProcessInfo>,
         let mut found_processes: Vec<ProcessInfo> = Vec::new();
 
         for &pid in &pids[..num_processes] {
+            if deadline.is_expired() {
+                break;
+            }
             if pid == 0 || pid == current_pid {
                 continue;
             }
@@ -241,6 +280,7 @@ pub fn get_locking_processes_low_level(path: &Path) -> Result<Vec<ProcessInfo>,
                     let exe_path = get_process_exe_path(pid);
                     found_processes.push(ProcessInfo {
                         process_id: pid,
+                        start_time: process_creation_time(pid),
                         executable_name: exe_path.clone(),
                         application_name: exe_path,
                     });
@@ -252,10 +292,36 @@ pub fn get_locking_processes_low_level(path: &Path) -> Result<Vec<ProcessInfo>,
     }
 }
 
+// RTL_USER_PROCESS_PARAMETERS layout. CurrentDirectory is a CURDIR structure, which
+// contains a UNICODE_STRING at the start.
+#[cfg(target_pointer_width = "64")]
+const CURRENT_DIRECTORY_OFFSET: usize = 0x38;
+#[cfg(target_pointer_width = "32")]
+const CURRENT_DIRECTORY_OFFSET: usize = 0x24;
+#[cfg(target_pointer_width = "64")]
+const COMMAND_LINE_OFFSET: usize = 0x70;
+#[cfg(target_pointer_width = "32")]
+const COMMAND_LINE_OFFSET: usize = 0x40;
+#[cfg(target_pointer_width = "64")]
+const ENVIRONMENT_OFFSET: usize = 0x80;
+#[cfg(target_pointer_width = "32")]
+const ENVIRONMENT_OFFSET: usize = 0x48;
+#[cfg(target_pointer_width = "64")]
+const ENVIRONMENT_SIZE_OFFSET: usize = 0x3F0;
+#[cfg(target_pointer_width = "32")]
+const ENVIRONMENT_SIZE_OFFSET: usize = 0x290;
+
+/// Parameters a process was started with, read from its PEB.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ProcessParameters {
+    pub current_directory: Option<String>,
+    pub command_line: Option<String>,
+    /// Environment block of `NAME=value` strings, each null terminated, ending with a null.
+    pub environment: Option<Vec<u16>>,
+}
+
 /// Get the current working directory of a process by reading its PEB
 fn get_process_current_directory(pid: u32) -> Option<String> {
-    use windows::Win32::System::Diagnostics::Debug::ReadProcessMemory;
-
     unsafe {
         // Open the process with read access
         let process_handle =
@@ -265,94 +331,164 @@ fn get_process_current_directory(pid: u32) -> Option<String> {
             let _ = CloseHandle(h);
         });
 
-        // Get the PEB address
-        let mut pbi = ProcessBasicInformation {
-            exit_status: 0,
-            peb_base_address: std::ptr::null_mut(),
-            affinity_mask: 0,
-            base_priority: 0,
-            unique_process_id: 0,
-            inherited_from_unique_process_id: 0,
+        let process_parameters_ptr = read_process_parameters_ptr(process_handle)?;
+        read_unicode_string(
+            process_handle,
+            process_parameters_ptr + CURRENT_DIRECTORY_OFFSET,
+        )
+    }
+}
+
+/// Get the working directory, command line and environment of a process by reading its PEB
+pub(crate) fn get_process_parameters(pid: u32) -> Option<ProcessParameters> {
+    unsafe {
+        let process_handle =
+            OpenProcess(PROCESS_QUERY_INFORMATION | PROCESS_VM_READ, false, pid).ok()?;
+
+        let _guard = scopeguard::guard(process_handle, |h| {
+            let _ = CloseHandle(h);
+        });
+
+        let process_parameters_ptr = read_process_parameters_ptr(process_handle)?;
+
+        let environment_ptr: usize =
+            read_value(process_handle, process_parameters_ptr + ENVIRONMENT_OFFSET)?;
+        let environment_size: usize = read_value(
+            process_handle,
+            process_parameters_ptr + ENVIRONMENT_SIZE_OFFSET,
+        )?;
+        let environment = if environment_ptr == 0 || environment_size == 0 {
+            None
+        } else {
+            read_wide_buffer(process_handle, environment_ptr, environment_size)
         };
-        let mut return_length: u32 = 0;
 
-        let status = NtQueryInformationProcess(
+        Some(ProcessParameters {
+            current_directory: read_unicode_string(
+                process_handle,
+                process_parameters_ptr + CURRENT_DIRECTORY_OFFSET,
+            ),
+            command_line: read_unicode_string(
+                process_handle,
+                process_parameters_ptr + COMMAND_LINE_OFFSET,
+            ),
+            environment,
+        })
+    }
+}
+
+/// Get the address of the RTL_USER_PROCESS_PARAMETERS of a process from its PEB
+unsafe fn read_process_parameters_ptr(process_handle: HANDLE) -> Option<usize> {
+    // Get the PEB address
+    let mut pbi = ProcessBasicInformation {
+        exit_status: 0,
+        peb_base_address: std::ptr::null_mut(),
+        affinity_mask: 0,
+        base_priority: 0,
+        unique_process_id: 0,
+        inherited_from_unique_process_id: 0,
+    };
+    let mut return_length: u32 = 0;
+
+    let status = unsafe {
+        NtQueryInformationProcess(
             process_handle,
             PROCESS_BASIC_INFORMATION_CLASS,
             &mut pbi as *mut _ as *mut _,
             std::mem::size_of::<ProcessBasicInformation>() as u32,
             &mut return_length,
-        );
-
-        if status.is_err() || pbi.peb_base_address.is_null() {
-            return None;
-        }
+        )
+    };
 
-        // Read the PEB to get RTL_USER_PROCESS_PARAMETERS pointer
-        // PEB layout (64-bit): offset 0x20 contains ProcessParameters pointer
-        // PEB layout (32-bit): offset 0x10 contains ProcessParameters pointer
-        #[cfg(target_pointer_width = "64")]
-        const PROCESS_PARAMETERS_OFFSET: usize = 0x20;
-        #[cfg(target_pointer_width = "32")]
-        const PROCESS_PARAMETERS_OFFSET: usize = 0x10;
+    if status.is_err() || pbi.peb_base_address.is_null() {
+        return None;
+    }
 
-        let mut process_parameters_ptr: usize = 0;
-        let mut bytes_read: usize = 0;
+    // Read the PEB to get RTL_USER_PROCESS_PARAMETERS pointer
+    // PEB layout (64-bit): offset 0x20 contains ProcessParameters pointer
+    // PEB layout (32-bit): offset 0x10 contains ProcessParameters pointer
+    #[cfg(target_pointer_width = "64")]
+    const PROCESS_PARAMETERS_OFFSET: usize = 0x20;
+    #[cfg(target_pointer_width = "32")]
+    const PROCESS_PARAMETERS_OFFSET: usize = 0x10;
 
-        let result = ReadProcessMemory(
+    let process_parameters_ptr: usize = unsafe {
+        read_value(
             process_handle,
-            (pbi.peb_base_address as usize + PROCESS_PARAMETERS_OFFSET) as *const _,
-            &mut process_parameters_ptr as *mut _ as *mut _,
-            std::mem::size_of::<usize>(),
-            Some(&mut bytes_read),
-        );
+            pbi.peb_base_address as usize + PROCESS_PARAMETERS_OFFSET,
+        )?
+    };
 
-        if result.is_err() || process_parameters_ptr == 0 {
-            return None;
-        }
+    (process_parameters_ptr != 0).then_some(process_parameters_ptr)
+}
 
-        // Read the CurrentDirectory from RTL_USER_PROCESS_PARAMETERS
-        // CurrentDirectory is a CURDIR structure at offset 0x38 (64-bit) or 0x24 (32-bit)
-        // CURDIR contains UNICODE_STRING at the start
-        #[cfg(target_pointer_width = "64")]
-        const CURRENT_DIRECTORY_OFFSET: usize = 0x38;
-        #[cfg(target_pointer_width = "32")]
-        const CURRENT_DIRECTORY_OFFSET: usize = 0x24;
+/// Read a plain value from the memory of another process
+unsafe fn read_value<T: Default>(process_handle: HANDLE, address: usize) -> Option<T> {
+    use windows::Win32::System::Diagnostics::Debug::ReadProcessMemory;
+
+    let mut value = T::default();
+    let mut bytes_read: usize = 0;
 
-        let mut unicode_string = UNICODE_STRING::default();
-        let result = ReadProcessMemory(
+    unsafe {
+        ReadProcessMemory(
             process_handle,
-            (process_parameters_ptr + CURRENT_DIRECTORY_OFFSET) as *const _,
-            &mut unicode_string as *mut _ as *mut _,
-            std::mem::size_of::<UNICODE_STRING>(),
+            address as *const _,
+            &mut value as *mut _ as *mut _,
+            std::mem::size_of::<T>(),
             Some(&mut bytes_read),
-        );
+        )
+        .ok()?;
+    }
 
-        if result.is_err() || unicode_string.Length == 0 || unicode_string.Buffer.is_null() {
-            return None;
-        }
+    Some(value)
+}
 
-        // Read the actual string
-        let len = (unicode_string.Length / 2) as usize;
-        let mut buffer: Vec<u16> = vec![0; len];
+/// Read a UNICODE_STRING located at `address` in the memory of another process
+unsafe fn read_unicode_string(process_handle: HANDLE, address: usize) -> Option<String> {
+    let unicode_string: UNICODE_STRING = unsafe { read_value(process_handle, address)? };
 
-        let result = ReadProcessMemory(
+    if unicode_string.Length == 0 || unicode_string.Buffer.is_null() {
+        return None;
+    }
+
+    // Read the actual string
+    let buffer = unsafe {
+        read_wide_buffer(
             process_handle,
-            unicode_string.Buffer.0 as *const _,
-            buffer.as_mut_ptr() as *mut _,
+            unicode_string.Buffer.0 as usize,
             unicode_string.Length as usize,
-            Some(&mut bytes_read),
-        );
+        )?
+    };
 
-        if result.is_err() {
-            return None;
-        }
+    String::from_utf16(&buffer).ok()
+}
 
-        String::from_utf16(&buffer).ok()
+/// Read `byte_length` bytes of UTF-16 data from the memory of another process
+unsafe fn read_wide_buffer(
+    process_handle: HANDLE,
+    address: usize,
+    byte_length: usize,
+) -> Option<Vec<u16>> {
+    use windows::Win32::System::Diagnostics::Debug::ReadProcessMemory;
+
+    let mut buffer: Vec<u16> = vec![0; byte_length / 2];
+    let mut bytes_read: usize = 0;
+
+    unsafe {
+        ReadProcessMemory(
+            process_handle,
+            address as *const _,
+            buffer.as_mut_ptr() as *mut _,
+            buffer.len() * 2,
+            Some(&mut bytes_read),
+        )
+        .ok()?;
     }
+
+    Some(buffer)
 }
 
-fn get_process_exe_path(pid: u32) -> Option<String> {
+pub(crate) fn get_process_exe_path(pid: u32) -> Option<String> {
     unsafe {
         let handle = OpenProcess(PROCESS_QUERY_INFORMATION | PROCESS_VM_READ, false, pid).ok()?;
 
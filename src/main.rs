@@ -1,13 +1,25 @@
 use anyhow::Result;
 use clap::Parser;
-use fops::cli::{Cli, Commands};
-use fops::config::ForceOpsConfig;
-use fops::deleter::FileAndDirectoryDeleter;
-use fops::elevation;
-use fops::lock_checker;
+use fops::cli::{Cli, Commands, QuarantineCommands};
+use fops::config::{ForceOpsConfig, LockHolderAction};
+use fops::deleter::{DeleteError, DeleteFailures, FileAndDirectoryDeleter};
+use fops::elevation::{self, ElevatedProcessFailed, ElevatedResult};
+use fops::filter::PathFilter;
+use fops::lock_checker::{self, format_processes};
+use fops::quarantine;
+use fops::report::{DeleteReport, DryRunReport, PlannedAction};
+use fops::restart::{self, RestartOutcome};
+use fops::retry::{RetryPolicies, RetryPolicy};
+use fops::tombstone;
 use fops::utils;
+use std::cell::RefCell;
+use std::path::PathBuf;
 use std::process::ExitCode;
-use tracing::error;
+use std::time::{Duration, UNIX_EPOCH};
+use tracing::{error, info, warn};
+
+/// Exit code when the deletion stopped at its timeout, see PLAN.md.
+const EXIT_CODE_TIMED_OUT: u8 = 3;
 
 fn main() -> ExitCode {
     tracing_subscriber::fmt()
@@ -25,47 +37,286 @@ fn main() -> ExitCode {
         Ok(()) => ExitCode::SUCCESS,
         Err(e) => {
             error!("{e}");
-            ExitCode::from(1)
+            if is_protected(&e) {
+                info!("Use --no-preserve-root to delete it anyway.");
+            }
+            ExitCode::from(exit_code(&e))
         }
     }
 }
 
+fn exit_code(error: &anyhow::Error) -> u8 {
+    if let Some(DeleteError::TimedOut { .. }) = error.downcast_ref::<DeleteError>() {
+        return EXIT_CODE_TIMED_OUT;
+    }
+    if error
+        .downcast_ref::<DeleteFailures>()
+        .is_some_and(DeleteFailures::timed_out)
+    {
+        return EXIT_CODE_TIMED_OUT;
+    }
+    match error.downcast_ref::<ElevatedProcessFailed>() {
+        Some(failed) if failed.exit_code == u32::from(EXIT_CODE_TIMED_OUT) => EXIT_CODE_TIMED_OUT,
+        _ => 1,
+    }
+}
+
+/// Whether a target was refused as protected, which `--no-preserve-root` overrides.
+fn is_protected(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<DeleteError>(),
+        Some(DeleteError::Protected { .. })
+    ) || error
+        .downcast_ref::<DeleteFailures>()
+        .is_some_and(DeleteFailures::protected)
+}
+
 fn run(cli: Cli) -> Result<()> {
     match cli.command {
         Commands::Delete {
             files,
             force,
             disable_elevate,
+            no_preserve_root,
             retry_delay,
             max_retries,
+            backoff,
+            max_retry_delay,
+            retry_jitter,
+            locked_retries,
+            permission_retries,
+            racing_retries,
+            timeout,
+            path_timeout,
+            keep_going,
+            kill_tree,
+            restart_killed,
+            suspend,
+            close_handles,
+            dry_run,
+            exclude,
+            include,
+            contents_only,
+            one_file_system,
+            follow_links,
+            trash,
+            quarantine,
+            background,
+            shred,
+            shred_passes,
+            shred_pattern,
+            jobs,
+            elevated_result,
+            capture_killed,
         } => {
             let config = ForceOpsConfig {
-                max_retries,
-                retry_delay_ms: retry_delay,
+                retry_policy: {
+                    let policy = |retries: Option<u32>| RetryPolicy {
+                        max_retries: retries.unwrap_or(max_retries),
+                        initial_delay: Duration::from_millis(retry_delay),
+                        backoff,
+                        max_delay: Duration::from_millis(max_retry_delay),
+                        jitter: retry_jitter,
+                    };
+                    RetryPolicies {
+                        locked: policy(locked_retries),
+                        permission: policy(permission_retries),
+                        racing: policy(racing_retries),
+                    }
+                },
+                timeout,
+                path_timeout,
+                keep_going,
+                preserve_root: !no_preserve_root,
                 disable_elevate,
+                lock_holder_action: if suspend {
+                    LockHolderAction::Suspend
+                } else if close_handles {
+                    LockHolderAction::CloseHandles
+                } else {
+                    LockHolderAction::Kill
+                },
+                kill_process_tree: kill_tree,
+                close_handles_when_suspended: suspend && close_handles,
+                restart_killed: restart_killed || capture_killed,
+                filter: PathFilter::new(&include, &exclude)?,
+                contents_only,
+                one_file_system,
+                follow_links,
+                trash,
+                quarantine,
+                tombstone: background,
+                shred_passes: if shred { shred_passes } else { 0 },
+                shred_pattern,
+                jobs,
             };
 
-            let run_delete = || -> Result<()> {
-                let deleter = FileAndDirectoryDeleter::new(config.clone());
+            let lock_holder_action = config.lock_holder_action;
+            let background_config = config.clone();
+            let deleter = FileAndDirectoryDeleter::new(config);
+
+            if dry_run {
+                let mut report = DryRunReport::default();
                 for file in &files {
                     let path = utils::combine_with_cwd_and_get_absolute_path(file);
-                    deleter.delete_file_or_directory(&path, force)?;
+                    report.merge(deleter.dry_run(&path, force)?);
+                }
+                print_dry_run_report(&report, lock_holder_action);
+                return Ok(());
+            }
+
+            // The report of this process, merged with the one of the elevated process if relaunched
+            let delete_report = RefCell::new(DeleteReport::new());
+            let run_delete = || -> Result<()> {
+                let paths: Vec<PathBuf> = files
+                    .iter()
+                    .map(|f| utils::combine_with_cwd_and_get_absolute_path(f))
+                    .collect();
+
+                // Clean up tombstones left by earlier background deletions next to the targets
+                let mut tombstones = if background {
+                    tombstone::find_leftover_tombstones(&paths)
+                } else {
+                    Vec::new()
+                };
+
+                let outcome = deleter.delete_all(&paths, force);
+                let report = outcome.report;
+                for remaining in outcome
+                    .not_attempted
+                    .iter()
+                    .filter(|p| utils::entry_exists(p))
+                {
+                    warn!("Not deleted: '{}'", remaining.display());
+                }
+
+                tombstones.extend(report.tombstones.iter().cloned());
+                if !tombstones.is_empty() {
+                    info!(
+                        "Deleting {} tombstone(s) in the background.",
+                        tombstones.len()
+                    );
+                    if let Err(e) = tombstone::delete_in_background(
+                        &tombstones,
+                        &background_config,
+                        deleter.deadline(),
+                    ) {
+                        warn!(
+                            "Failed to start deleting tombstones in the background: {}",
+                            e
+                        );
+                    }
+                }
+                delete_report.borrow_mut().merge(report);
+
+                // Fail once, with every failure when going on after them
+                let mut failures = outcome.failures;
+                match failures.len() {
+                    0 => Ok(()),
+                    1 => Err(failures.remove(0).into()),
+                    _ => Err(DeleteFailures(failures).into()),
                 }
-                Ok(())
             };
 
-            if disable_elevate {
-                run_delete()?;
+            let mut elevated_killed = Vec::new();
+            let result = if disable_elevate {
+                run_delete()
             } else {
-                elevation::run_with_relaunch_as_elevated(run_delete, || {
-                    let mut args: Vec<String> = std::env::args().collect();
-                    if !args.iter().any(|a| a == "-f" || a == "--force") {
-                        args.push("-f".to_string());
-                    }
-                    args
-                })?;
+                elevation::run_with_relaunch_as_elevated(
+                    run_delete,
+                    || {
+                        let mut args: Vec<String> = std::env::args().collect();
+                        if !args.iter().any(|a| a == "-f" || a == "--force") {
+                            args.push("-f".to_string());
+                        }
+                        // The processes killed are restarted here, so they do not come back
+                        // elevated
+                        args.retain(|a| a != "--restart-killed");
+                        if restart_killed {
+                            args.push("--capture-killed".to_string());
+                        }
+                        // The elevated process only gets the time left
+                        if let Some(remaining) = deleter.deadline().remaining() {
+                            args.push(format!("--timeout={}ms", remaining.as_millis()));
+                        }
+                        args
+                    },
+                    |elevated| {
+                        elevated_killed = elevated.killed_processes;
+                        delete_report.borrow_mut().merge(elevated.report);
+                    },
+                )
+            };
+
+            let report = delete_report.into_inner();
+            for excluded in &report.excluded {
+                info!("Kept '{}' (excluded)", excluded.display());
+            }
+            for mount_point in &report.mount_points {
+                info!("Kept '{}' (mount point)", mount_point.display());
             }
+            for (path, id) in &report.quarantined {
+                info!(
+                    "Quarantined '{}' as {}. Restore it with `fops restore {}`",
+                    path.display(),
+                    id,
+                    id
+                );
+            }
+            info!("{}", report);
+
+            // Restart after any elevated relaunch, so restarted processes cannot hold locks again
+            deleter.resume_suspended_processes();
+            if let Some(result_file) = &elevated_result {
+                let elevated = ElevatedResult {
+                    killed_processes: deleter.take_killed_processes(),
+                    report,
+                };
+                if let Err(e) = elevated.write(result_file) {
+                    warn!("Failed to write the elevated result: {}", e);
+                }
+            } else {
+                let mut outcomes = deleter.restart_killed_processes();
+                outcomes.extend(restart::restart_processes(&elevated_killed));
+                log_restart_outcomes(&outcomes);
+            }
+            result?;
+        }
+        Commands::Restore { id } => {
+            let manifest = quarantine::restore(&id)?;
+            info!(
+                "Restored '{}' from quarantine.",
+                manifest.original_path.display()
+            );
         }
+        Commands::Quarantine { command } => match command {
+            QuarantineCommands::List => {
+                println!("Id,QuarantinedAt,OriginalPath");
+                for manifest in quarantine::list()? {
+                    println!(
+                        "{},{},{}",
+                        manifest.id,
+                        manifest
+                            .timestamp
+                            .duration_since(UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_secs(),
+                        manifest.original_path.display()
+                    );
+                }
+            }
+            QuarantineCommands::Purge { older_than } => {
+                let purged = quarantine::purge(older_than)?;
+                for manifest in &purged {
+                    info!(
+                        "Purged {} ('{}')",
+                        manifest.id,
+                        manifest.original_path.display()
+                    );
+                }
+                info!("Purged {} quarantined items.", purged.len());
+            }
+        },
         Commands::List { file_or_directory } => {
             let path = utils::combine_with_cwd_and_get_absolute_path(&file_or_directory);
             let processes = lock_checker::get_locks(&path)?;
@@ -84,3 +335,86 @@ fn run(cli: Cli) -> Result<()> {
 
     Ok(())
 }
+
+fn log_restart_outcomes(outcomes: &[RestartOutcome]) {
+    for outcome in outcomes {
+        let launch_info = &outcome.launch_info;
+        match &outcome.result {
+            Ok(new_pid) => info!(
+                "Restarted killed process {} as process {}: {}",
+                launch_info.process_id, new_pid, launch_info.command_line
+            ),
+            Err(e) => warn!(
+                "Failed to restart killed process {} ({}): {}",
+                launch_info.process_id, launch_info.executable, e
+            ),
+        }
+    }
+
+    if !outcomes.is_empty() {
+        let restarted = outcomes.iter().filter(|o| o.result.is_ok()).count();
+        info!(
+            "Restarted {} of {} killed processes.",
+            restarted,
+            outcomes.len()
+        );
+    }
+}
+
+fn print_dry_run_report(report: &DryRunReport, lock_holder_action: LockHolderAction) {
+    for entry in &report.entries {
+        let path = entry.path.display();
+        match entry.action {
+            PlannedAction::Delete => println!("Would delete '{}'", path),
+            PlannedAction::Trash => println!("Would move '{}' to the Recycle Bin", path),
+            PlannedAction::Quarantine => println!("Would move '{}' into quarantine", path),
+            PlannedAction::Tombstone => println!(
+                "Would rename '{}' to a tombstone and delete it in the background",
+                path
+            ),
+        }
+    }
+
+    for excluded in &report.excluded {
+        println!("Would keep '{}' (excluded)", excluded.display());
+    }
+
+    for mount_point in &report.mount_points {
+        println!("Would keep '{}' (mount point)", mount_point.display());
+    }
+
+    for locked_entry in &report.locked_entries {
+        println!(
+            "Locked '{}' by [{}]",
+            locked_entry.path.display(),
+            format_processes(&locked_entry.processes)
+        );
+    }
+
+    for process in report.locking_processes() {
+        println!("Would {} process {}", lock_holder_action.verb(), process);
+    }
+
+    let deleted = report
+        .entries
+        .iter()
+        .filter(|e| e.action == PlannedAction::Delete)
+        .count();
+    let moved = report.entries.len() - deleted;
+    println!(
+        "Would delete {} {}{}, {} locked. Elevation would {}be required.",
+        deleted,
+        entries_noun(deleted),
+        if moved > 0 {
+            format!(" and move {} {}", moved, entries_noun(moved))
+        } else {
+            String::new()
+        },
+        report.locked_entries.len(),
+        if report.needs_elevation { "" } else { "not " }
+    );
+}
+
+fn entries_noun(count: usize) -> &'static str {
+    if count == 1 { "entry" } else { "entries" }
+}
@@ -1,39 +1,288 @@
 //! Process termination utilities
 
 use crate::lock_checker::ProcessInfo;
+use std::collections::{HashMap, HashSet};
 use std::process;
-use tracing::warn;
-use windows::Win32::Foundation::{CloseHandle, HANDLE};
+use tracing::{info, warn};
+use windows::Win32::Foundation::{CloseHandle, FILETIME, HANDLE};
+use windows::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, PROCESSENTRY32W, Process32FirstW, Process32NextW, TH32CS_SNAPPROCESS,
+};
 use windows::Win32::System::Threading::{
-    GetCurrentProcessId, OpenProcess, PROCESS_TERMINATE, TerminateProcess,
+    GetCurrentProcessId, GetProcessTimes, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    PROCESS_TERMINATE, TerminateProcess,
 };
 
-/// Kills the specified processes.
+/// The outcome of trying to kill a process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KillOutcome {
+    Killed,
+    /// The process exited and its ID was reused by another process after lock detection.
+    Skipped,
+    Failed(String),
+}
+
+/// A process that fops tried to kill
+#[derive(Debug, Clone)]
+pub struct KillResult {
+    pub process_info: ProcessInfo,
+    pub outcome: KillOutcome,
+}
+
+/// Kills the specified processes, returning the outcome for each of them.
 ///
 /// Skips the current process and handles errors gracefully.
-pub fn kill_processes(processes: &[ProcessInfo]) {
+pub fn kill_processes(processes: &[ProcessInfo]) -> Vec<KillResult> {
     let current_pid = unsafe { GetCurrentProcessId() };
+    let mut results = Vec::new();
 
     for process_info in processes {
         if process_info.process_id == current_pid {
             continue;
         }
 
-        if let Err(e) = kill_process(process_info.process_id) {
-            warn!("Failed to kill process {}: {}", process_info.process_id, e);
+        let outcome = match kill_process(process_info) {
+            Ok(true) => KillOutcome::Killed,
+            Ok(false) => KillOutcome::Skipped,
+            Err(e) => {
+                warn!("Failed to kill process {}: {}", process_info.process_id, e);
+                KillOutcome::Failed(e)
+            }
+        };
+        results.push(KillResult {
+            process_info: process_info.clone(),
+            outcome,
+        });
+    }
+
+    results
+}
+
+/// Kills the specified processes together with all of their descendants.
+///
+/// Descendants are discovered from a snapshot of the process table and are killed before
+/// their ancestors, so a parent cannot respawn a child that has just been killed.
+/// Every process in the tree goes through the same checks as [`kill_processes`].
+pub fn kill_process_trees(processes: &[ProcessInfo]) -> Vec<KillResult> {
+    let entries = match snapshot_processes() {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!(
+                "Failed to enumerate processes, only killing the locking processes: {}",
+                e
+            );
+            return kill_processes(processes);
+        }
+    };
+
+    let children = build_children_map(&entries);
+    let mut visited = HashSet::new();
+    let mut ordered = Vec::new();
+
+    for process_info in processes {
+        let descendants_before = ordered.len();
+        collect_descendants(
+            process_info.process_id,
+            &children,
+            &mut visited,
+            &mut ordered,
+        );
+        let descendant_count = ordered.len() - descendants_before;
+
+        if descendant_count > 0 {
+            info!(
+                "Killing process tree of {}: found {} descendant {}.",
+                process_info.process_id,
+                descendant_count,
+                if descendant_count == 1 {
+                    "process"
+                } else {
+                    "processes"
+                }
+            );
+        }
+
+        if visited.insert(process_info.process_id) {
+            ordered.push(process_info.clone());
         }
     }
+
+    kill_processes(&ordered)
 }
 
-fn kill_process(pid: u32) -> Result<(), String> {
+/// An entry of the process table.
+struct ProcessEntry {
+    process_id: u32,
+    parent_process_id: u32,
+    executable_name: Option<String>,
+}
+
+fn snapshot_processes() -> windows::core::Result<Vec<ProcessEntry>> {
     unsafe {
-        let handle: HANDLE =
-            OpenProcess(PROCESS_TERMINATE, false, pid).map_err(|e| format!("{}", e))?;
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0)?;
+        let mut entries = Vec::new();
+
+        let mut entry = PROCESSENTRY32W {
+            dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+            ..Default::default()
+        };
+
+        let mut result = Process32FirstW(snapshot, &mut entry);
+        while result.is_ok() {
+            let len = entry
+                .szExeFile
+                .iter()
+                .position(|&c| c == 0)
+                .unwrap_or(entry.szExeFile.len());
+
+            entries.push(ProcessEntry {
+                process_id: entry.th32ProcessID,
+                parent_process_id: entry.th32ParentProcessID,
+                executable_name: String::from_utf16(&entry.szExeFile[..len]).ok(),
+            });
+
+            result = Process32NextW(snapshot, &mut entry);
+        }
+
+        let _ = CloseHandle(snapshot);
+        Ok(entries)
+    }
+}
+
+/// Maps each process ID to its children.
+///
+/// Windows reuses process IDs, so a recorded parent ID may belong to an unrelated process
+/// that was started after the child. Such links are dropped by comparing creation times.
+fn build_children_map(entries: &[ProcessEntry]) -> HashMap<u32, Vec<ProcessInfo>> {
+    let creation_times: HashMap<u32, u64> = entries
+        .iter()
+        .filter_map(|e| process_creation_time(e.process_id).map(|t| (e.process_id, t)))
+        .collect();
+
+    let mut children: HashMap<u32, Vec<ProcessInfo>> = HashMap::new();
+    for entry in entries {
+        if entry.process_id == 0 || entry.process_id == entry.parent_process_id {
+            continue;
+        }
+
+        if let (Some(parent_time), Some(child_time)) = (
+            creation_times.get(&entry.parent_process_id),
+            creation_times.get(&entry.process_id),
+        ) && child_time < parent_time
+        {
+            continue;
+        }
+
+        children
+            .entry(entry.parent_process_id)
+            .or_default()
+            .push(ProcessInfo {
+                process_id: entry.process_id,
+                start_time: creation_times.get(&entry.process_id).copied(),
+                executable_name: entry.executable_name.clone(),
+                application_name: entry.executable_name.clone(),
+            });
+    }
+
+    children
+}
+
+/// Appends the descendants of `pid` to `ordered` in post-order (children before parents).
+fn collect_descendants(
+    pid: u32,
+    children: &HashMap<u32, Vec<ProcessInfo>>,
+    visited: &mut HashSet<u32>,
+    ordered: &mut Vec<ProcessInfo>,
+) {
+    let Some(direct_children) = children.get(&pid) else {
+        return;
+    };
+
+    for child in direct_children {
+        if !visited.insert(child.process_id) {
+            continue;
+        }
+        collect_descendants(child.process_id, children, visited, ordered);
+        ordered.push(child.clone());
+    }
+}
+
+/// Checks whether this process has the rights to terminate a process.
+pub fn can_terminate(process_info: &ProcessInfo) -> bool {
+    unsafe {
+        match OpenProcess(PROCESS_TERMINATE, false, process_info.process_id) {
+            Ok(handle) => {
+                let _ = CloseHandle(handle);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// Gets the creation time of a process as a FILETIME tick count.
+pub(crate) fn process_creation_time(pid: u32) -> Option<u64> {
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+        let time = process_creation_time_from_handle(handle);
+        let _ = CloseHandle(handle);
+        time
+    }
+}
+
+pub(crate) unsafe fn process_creation_time_from_handle(handle: HANDLE) -> Option<u64> {
+    let mut creation_time = FILETIME::default();
+    let mut exit_time = FILETIME::default();
+    let mut kernel_time = FILETIME::default();
+    let mut user_time = FILETIME::default();
+
+    unsafe {
+        GetProcessTimes(
+            handle,
+            &mut creation_time,
+            &mut exit_time,
+            &mut kernel_time,
+            &mut user_time,
+        )
+        .ok()?;
+    }
+
+    Some(filetime_to_ticks(creation_time))
+}
+
+pub(crate) fn filetime_to_ticks(filetime: FILETIME) -> u64 {
+    ((filetime.dwHighDateTime as u64) << 32) | filetime.dwLowDateTime as u64
+}
+
+/// Kills a single process, first verifying that the process ID still refers to the
+/// process that was detected, as the ID may have been reused since then.
+/// Returns false if the process was skipped.
+fn kill_process(process_info: &ProcessInfo) -> Result<bool, String> {
+    let pid = process_info.process_id;
+    let access = if process_info.start_time.is_some() {
+        PROCESS_TERMINATE | PROCESS_QUERY_LIMITED_INFORMATION
+    } else {
+        PROCESS_TERMINATE
+    };
+
+    unsafe {
+        let handle: HANDLE = OpenProcess(access, false, pid).map_err(|e| format!("{}", e))?;
 
         if handle.is_invalid() {
             return Err("Failed to open process".to_string());
         }
 
+        if let Some(expected_start_time) = process_info.start_time
+            && process_creation_time_from_handle(handle) != Some(expected_start_time)
+        {
+            let _ = CloseHandle(handle);
+            warn!(
+                "Skipping process {}: the process exited and its ID was reused after lock detection",
+                pid
+            );
+            return Ok(false);
+        }
+
         let result = TerminateProcess(handle, 1);
         let _ = CloseHandle(handle);
 
@@ -41,7 +290,7 @@ fn kill_process(pid: u32) -> Result<(), String> {
             return Err(e.to_string());
         }
 
-        Ok(())
+        Ok(true)
     }
 }
 
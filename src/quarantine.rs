@@ -0,0 +1,499 @@
+//! A fops-managed quarantine directory that deleted files and directories can be restored from
+//!
+//! Each quarantined target gets its own entry directory, holding the target as `item` and a
+//! line-based `manifest.txt` recording where it came from.
+
+use crate::lock_checker::ProcessInfo;
+use remove_dir_all::remove_dir_all as fast_remove_dir_all;
+use std::fs;
+use std::io::{self, Read};
+use std::mem::size_of;
+use std::os::windows::ffi::OsStrExt;
+use std::os::windows::fs::{FileTypeExt, OpenOptionsExt, symlink_dir, symlink_file};
+use std::os::windows::io::AsRawHandle;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tracing::warn;
+use windows::Win32::Foundation::HANDLE;
+use windows::Win32::Security::Cryptography::{
+    BCRYPT_HASH_HANDLE, BCRYPT_SHA256_ALG_HANDLE, BCryptCreateHash, BCryptDestroyHash,
+    BCryptFinishHash, BCryptHashData,
+};
+use windows::Win32::Storage::FileSystem::{
+    FILE_ATTRIBUTE_TAG_INFO, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT,
+    FILE_READ_ATTRIBUTES, FileAttributeTagInfo, GetFileInformationByHandleEx,
+};
+use windows::Win32::System::IO::DeviceIoControl;
+
+const MANIFEST_FILE_NAME: &str = "manifest.txt";
+const ITEM_NAME: &str = "item";
+const ERROR_NOT_SAME_DEVICE: i32 = 17;
+const IO_REPARSE_TAG_MOUNT_POINT: u32 = 0xA000_0003;
+const FSCTL_SET_REPARSE_POINT: u32 = 0x0009_00A4;
+const MAXIMUM_REPARSE_DATA_BUFFER_SIZE: usize = 16 * 1024;
+
+static NEXT_ENTRY: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Error, Debug)]
+pub enum QuarantineError {
+    #[error("No quarantined item with id '{0}'")]
+    NotFound(String),
+
+    #[error("'{0}' is not a quarantine id")]
+    InvalidId(String),
+
+    #[error("Cannot restore to '{}' as it already exists", .0.display())]
+    DestinationExists(PathBuf),
+
+    #[error("Invalid quarantine manifest '{}': {reason}", .path.display())]
+    InvalidManifest { path: PathBuf, reason: String },
+
+    #[error("Failed to hash '{}': {source}", .path.display())]
+    Hash { path: PathBuf, source: io::Error },
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// What was quarantined, stored next to the item so it can be restored
+#[derive(Debug, Clone)]
+pub struct QuarantineManifest {
+    pub id: String,
+    /// Where the item was before it was quarantined.
+    pub original_path: PathBuf,
+    /// When the item was quarantined.
+    pub timestamp: SystemTime,
+    /// Processes killed to quarantine the item.
+    pub killed_processes: Vec<ProcessInfo>,
+    /// SHA-256 hashes of the quarantined files, by path relative to the item. Symlinks and
+    /// junctions are recorded as `link <target>`.
+    pub hashes: Vec<(PathBuf, String)>,
+}
+
+impl QuarantineManifest {
+    fn write(&self, entry_directory: &Path) -> io::Result<()> {
+        let mut contents = String::new();
+        contents.push_str(&format!(
+            "original_path\t{}\n",
+            self.original_path.display()
+        ));
+        let seconds = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        contents.push_str(&format!("timestamp\t{}\n", seconds));
+        for process in &self.killed_processes {
+            contents.push_str(&format!(
+                "killed\t{}\t{}\n",
+                process.process_id,
+                process.executable_name.as_deref().unwrap_or("")
+            ));
+        }
+        for (relative_path, hash) in &self.hashes {
+            contents.push_str(&format!("hash\t{}\t{}\n", relative_path.display(), hash));
+        }
+
+        fs::write(entry_directory.join(MANIFEST_FILE_NAME), contents)
+    }
+
+    fn read(id: &str, entry_directory: &Path) -> Result<Self, QuarantineError> {
+        let path = entry_directory.join(MANIFEST_FILE_NAME);
+        let invalid = |reason: &str| QuarantineError::InvalidManifest {
+            path: path.clone(),
+            reason: reason.to_string(),
+        };
+
+        let contents = fs::read_to_string(&path)?;
+        let mut original_path = None;
+        let mut timestamp = None;
+        let mut killed_processes = Vec::new();
+        let mut hashes = Vec::new();
+
+        for line in contents.lines() {
+            let mut fields = line.splitn(3, '\t');
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some("original_path"), Some(value), None) => {
+                    original_path = Some(PathBuf::from(value));
+                }
+                (Some("timestamp"), Some(value), None) => {
+                    let seconds = value.parse().map_err(|_| invalid("bad timestamp"))?;
+                    timestamp = Some(UNIX_EPOCH + Duration::from_secs(seconds));
+                }
+                (Some("killed"), Some(pid), Some(executable_name)) => {
+                    killed_processes.push(ProcessInfo {
+                        process_id: pid.parse().map_err(|_| invalid("bad process id"))?,
+                        start_time: None,
+                        executable_name: Some(executable_name.to_string()),
+                        application_name: None,
+                    });
+                }
+                (Some("hash"), Some(relative_path), Some(hash)) => {
+                    hashes.push((PathBuf::from(relative_path), hash.to_string()));
+                }
+                _ => return Err(invalid(&format!("unexpected line '{}'", line))),
+            }
+        }
+
+        Ok(Self {
+            id: id.to_string(),
+            original_path: original_path.ok_or_else(|| invalid("missing original_path"))?,
+            timestamp: timestamp.ok_or_else(|| invalid("missing timestamp"))?,
+            killed_processes,
+            hashes,
+        })
+    }
+}
+
+/// The directory holding quarantined items, `%LOCALAPPDATA%\fops\quarantine`.
+pub fn quarantine_directory() -> PathBuf {
+    std::env::var_os("LOCALAPPDATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("fops")
+        .join("quarantine")
+}
+
+/// A new, empty entry in the quarantine that an item can be moved into.
+pub(crate) struct QuarantineEntry {
+    pub id: String,
+    pub directory: PathBuf,
+}
+
+impl QuarantineEntry {
+    pub fn create() -> io::Result<Self> {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        // Keep in sync with is_valid_id
+        let id = format!(
+            "{}-{}-{}",
+            seconds,
+            std::process::id(),
+            NEXT_ENTRY.fetch_add(1, Ordering::Relaxed)
+        );
+        let directory = quarantine_directory().join(&id);
+        fs::create_dir_all(&directory)?;
+        Ok(Self { id, directory })
+    }
+
+    /// Where the quarantined file or directory is stored.
+    pub fn item_path(&self) -> PathBuf {
+        self.directory.join(ITEM_NAME)
+    }
+
+    /// Record the quarantined item, hashing its files.
+    pub fn finish(
+        self,
+        original_path: &Path,
+        killed_processes: Vec<ProcessInfo>,
+    ) -> io::Result<QuarantineManifest> {
+        // The item is already quarantined, so record it even if it cannot be hashed
+        let hashes = hash_tree(&self.item_path()).unwrap_or_else(|e| {
+            warn!("Failed to hash quarantined item '{}': {}", self.id, e);
+            Vec::new()
+        });
+        let manifest = QuarantineManifest {
+            id: self.id.clone(),
+            original_path: original_path.to_path_buf(),
+            timestamp: SystemTime::now(),
+            killed_processes,
+            hashes,
+        };
+        manifest.write(&self.directory)?;
+        Ok(manifest)
+    }
+
+    /// Remove the entry after failing to quarantine an item.
+    pub fn discard(self) {
+        let _ = fast_remove_dir_all(&self.directory);
+    }
+}
+
+/// List the quarantined items, oldest first.
+pub fn list() -> Result<Vec<QuarantineManifest>, QuarantineError> {
+    let directory = quarantine_directory();
+    if !directory.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut manifests = Vec::new();
+    for entry in fs::read_dir(&directory)? {
+        let entry = entry?;
+        let id = entry.file_name().to_string_lossy().to_string();
+        match QuarantineManifest::read(&id, &entry.path()) {
+            Ok(manifest) => manifests.push(manifest),
+            Err(e) => warn!("Skipping quarantine entry '{}': {}", id, e),
+        }
+    }
+
+    manifests.sort_by_key(|m| m.timestamp);
+    Ok(manifests)
+}
+
+/// Move a quarantined item back to where it came from.
+pub fn restore(id: &str) -> Result<QuarantineManifest, QuarantineError> {
+    // The entry is removed once restored, so it must not resolve outside the quarantine
+    if !is_valid_id(id) {
+        return Err(QuarantineError::InvalidId(id.to_string()));
+    }
+
+    let entry_directory = quarantine_directory().join(id);
+    if !entry_directory.join(MANIFEST_FILE_NAME).exists() {
+        return Err(QuarantineError::NotFound(id.to_string()));
+    }
+
+    let manifest = QuarantineManifest::read(id, &entry_directory)?;
+    if manifest.original_path.exists() {
+        return Err(QuarantineError::DestinationExists(
+            manifest.original_path.clone(),
+        ));
+    }
+
+    let item_path = entry_directory.join(ITEM_NAME);
+    match hash_tree(&item_path) {
+        Ok(hashes) if hashes == manifest.hashes => {}
+        Ok(_) => warn!(
+            "Quarantined item '{}' has changed since it was quarantined, restoring it anyway",
+            id
+        ),
+        Err(e) => warn!(
+            "Failed to check quarantined item '{}' for changes, restoring it anyway: {}",
+            id, e
+        ),
+    }
+
+    if let Some(parent) = manifest.original_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Removing the entry also removes the item if it had to be copied
+    rename_or_copy(&item_path, &manifest.original_path)?;
+    fast_remove_dir_all(&entry_directory)?;
+
+    Ok(manifest)
+}
+
+/// Whether an id has the `<seconds>-<process id>-<counter>` form of the ids fops creates.
+fn is_valid_id(id: &str) -> bool {
+    let parts: Vec<&str> = id.split('-').collect();
+    parts.len() == 3
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Permanently delete quarantined items older than `older_than`, returning what was purged.
+pub fn purge(older_than: Duration) -> Result<Vec<QuarantineManifest>, QuarantineError> {
+    let now = SystemTime::now();
+    let mut purged = Vec::new();
+
+    for manifest in list()? {
+        let age = now.duration_since(manifest.timestamp).unwrap_or_default();
+        if age >= older_than {
+            fast_remove_dir_all(quarantine_directory().join(&manifest.id))?;
+            purged.push(manifest);
+        }
+    }
+
+    Ok(purged)
+}
+
+/// Rename a file or directory, copying it instead when the destination is on another volume.
+/// Returns whether it was copied, in which case the source still needs to be deleted.
+pub(crate) fn rename_or_copy(from: &Path, to: &Path) -> io::Result<bool> {
+    match fs::rename(from, to) {
+        Ok(()) => Ok(false),
+        Err(e) if e.raw_os_error() == Some(ERROR_NOT_SAME_DEVICE) => {
+            if let Err(e) = copy_tree(from, to) {
+                let _ = fast_remove_dir_all(to);
+                let _ = fs::remove_file(to);
+                return Err(e);
+            }
+            Ok(true)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+    let file_type = fs::symlink_metadata(from)?.file_type();
+    if file_type.is_symlink() {
+        return copy_link(from, to, file_type);
+    }
+    if !file_type.is_dir() {
+        fs::copy(from, to)?;
+        return Ok(());
+    }
+
+    fs::create_dir(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        copy_tree(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+/// Recreate a symlink or junction pointing at the same target, rather than copying its target.
+fn copy_link(from: &Path, to: &Path, file_type: fs::FileType) -> io::Result<()> {
+    let target = fs::read_link(from)?;
+    if reparse_tag(from)? == IO_REPARSE_TAG_MOUNT_POINT {
+        create_junction(to, &target)
+    } else if file_type.is_symlink_dir() {
+        symlink_dir(&target, to)
+    } else {
+        symlink_file(&target, to)
+    }
+}
+
+/// The reparse tag of a reparse point, telling junctions apart from symlinks.
+fn reparse_tag(path: &Path) -> io::Result<u32> {
+    let file = fs::OpenOptions::new()
+        .access_mode(FILE_READ_ATTRIBUTES.0)
+        .custom_flags((FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT).0)
+        .open(path)?;
+    let mut info = FILE_ATTRIBUTE_TAG_INFO::default();
+    unsafe {
+        GetFileInformationByHandleEx(
+            HANDLE(file.as_raw_handle()),
+            FileAttributeTagInfo,
+            (&raw mut info).cast(),
+            size_of::<FILE_ATTRIBUTE_TAG_INFO>() as u32,
+        )
+    }
+    .map_err(|_| io::Error::last_os_error())?;
+    Ok(info.ReparseTag)
+}
+
+/// Create a junction at `link` to `target`, as read back by `fs::read_link`.
+fn create_junction(link: &Path, target: &Path) -> io::Result<()> {
+    // read_link gives `\\?\` where the reparse point stores the NT prefix `\??\`
+    let target: Vec<u16> = target.as_os_str().encode_wide().collect();
+    let verbatim_prefix: Vec<u16> = r"\\?\".encode_utf16().collect();
+    let print_name = target
+        .strip_prefix(verbatim_prefix.as_slice())
+        .unwrap_or(&target);
+    let substitute_name: Vec<u16> = r"\??\"
+        .encode_utf16()
+        .chain(print_name.iter().copied())
+        .collect();
+
+    // A REPARSE_DATA_BUFFER for a mount point, with both names null-terminated
+    let substitute_bytes = (substitute_name.len() * 2) as u16;
+    let print_bytes = (print_name.len() * 2) as u16;
+    let mut path_buffer = substitute_name;
+    path_buffer.push(0);
+    path_buffer.extend_from_slice(print_name);
+    path_buffer.push(0);
+    if path_buffer.len() * 2 + 8 > MAXIMUM_REPARSE_DATA_BUFFER_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidFilename,
+            "junction target too long",
+        ));
+    }
+    let mut buffer: Vec<u8> = Vec::new();
+    buffer.extend(IO_REPARSE_TAG_MOUNT_POINT.to_le_bytes());
+    buffer.extend((8 + path_buffer.len() as u16 * 2).to_le_bytes());
+    buffer.extend(0u16.to_le_bytes());
+    buffer.extend(0u16.to_le_bytes());
+    buffer.extend(substitute_bytes.to_le_bytes());
+    buffer.extend((substitute_bytes + 2).to_le_bytes());
+    buffer.extend(print_bytes.to_le_bytes());
+    buffer.extend(path_buffer.iter().flat_map(|c| c.to_le_bytes()));
+
+    fs::create_dir(link)?;
+    let result = fs::OpenOptions::new()
+        .write(true)
+        .custom_flags((FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT).0)
+        .open(link)
+        .and_then(|file| {
+            unsafe {
+                DeviceIoControl(
+                    HANDLE(file.as_raw_handle()),
+                    FSCTL_SET_REPARSE_POINT,
+                    Some(buffer.as_ptr().cast()),
+                    buffer.len() as u32,
+                    None,
+                    0,
+                    None,
+                    None,
+                )
+            }
+            .map_err(|_| io::Error::last_os_error())
+        });
+    if result.is_err() {
+        let _ = fs::remove_dir(link);
+    }
+    result
+}
+
+/// Hash every file in a tree, or a single file, by path relative to `root`. Symlinks and
+/// junctions are recorded by their target instead, as they may be dangling or lead outside.
+fn hash_tree(root: &Path) -> Result<Vec<(PathBuf, String)>, QuarantineError> {
+    let mut hashes = Vec::new();
+    hash_tree_into(root, root, &mut hashes)?;
+    hashes.sort();
+    Ok(hashes)
+}
+
+fn hash_tree_into(
+    root: &Path,
+    path: &Path,
+    hashes: &mut Vec<(PathBuf, String)>,
+) -> Result<(), QuarantineError> {
+    let hash_error = |source| QuarantineError::Hash {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let file_type = fs::symlink_metadata(path).map_err(hash_error)?.file_type();
+    let relative_path = path.strip_prefix(root).unwrap_or(path).to_path_buf();
+    if file_type.is_symlink() {
+        let target = fs::read_link(path).map_err(hash_error)?;
+        hashes.push((relative_path, format!("link {}", target.display())));
+    } else if file_type.is_dir() {
+        for entry in fs::read_dir(path).map_err(hash_error)? {
+            let entry = entry.map_err(hash_error)?;
+            hash_tree_into(root, &entry.path(), hashes)?;
+        }
+    } else {
+        hashes.push((relative_path, sha256_file(path).map_err(hash_error)?));
+    }
+    Ok(())
+}
+
+/// Compute the SHA-256 hash of a file as lowercase hex.
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut digest = [0u8; 32];
+
+    unsafe {
+        let mut hash = BCRYPT_HASH_HANDLE::default();
+        BCryptCreateHash(BCRYPT_SHA256_ALG_HANDLE, &mut hash, None, None, 0)
+            .ok()
+            .map_err(io::Error::other)?;
+
+        let result = (|| {
+            loop {
+                let read = file.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                BCryptHashData(hash, &buffer[..read], 0)
+                    .ok()
+                    .map_err(io::Error::other)?;
+            }
+            BCryptFinishHash(hash, &mut digest, 0)
+                .ok()
+                .map_err(io::Error::other)
+        })();
+
+        let _ = BCryptDestroyHash(hash);
+        result?;
+    }
+
+    Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
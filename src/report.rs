@@ -0,0 +1,281 @@
+//! Reports of what a deletion did, or would do
+
+use crate::elevation;
+use crate::handles::CloseHandlesResult;
+use crate::lock_checker::ProcessInfo;
+use crate::process::{KillOutcome, KillResult};
+use crate::suspend::{SuspendOutcome, SuspendResult};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// What deleting files and directories did.
+#[derive(Debug, Clone, Default)]
+pub struct DeleteReport {
+    /// Number of files and directories deleted.
+    pub entries_deleted: u64,
+    /// Total size of the deleted files in bytes.
+    pub bytes_freed: u64,
+    /// Number of retries used per path, for paths that needed retries.
+    pub retries: BTreeMap<PathBuf, u32>,
+    /// Processes holding locks that fops tried to kill, and the outcome.
+    pub killed_processes: Vec<KillResult>,
+    /// Processes holding locks that fops tried to suspend, and the outcome.
+    pub suspended_processes: Vec<SuspendResult>,
+    /// Processes holding locks whose handles fops tried to close, and the outcome.
+    pub closed_handles: Vec<CloseHandlesResult>,
+    /// Time taken to delete.
+    pub elapsed: Duration,
+    /// Whether the deletion ran as an elevated process.
+    pub elevated: bool,
+    /// Entries kept because they matched an exclude glob.
+    pub excluded: Vec<PathBuf>,
+    /// Quarantine ids of the targets moved into the quarantine, by original path.
+    pub quarantined: BTreeMap<PathBuf, String>,
+    /// Tombstones the targets were renamed to, which still need to be deleted.
+    pub tombstones: Vec<PathBuf>,
+    /// Mount points kept with their contents because they are on another volume.
+    pub mount_points: BTreeSet<PathBuf>,
+}
+
+impl DeleteReport {
+    /// Create an empty report for a deletion performed by this process.
+    pub fn new() -> Self {
+        Self {
+            elevated: elevation::is_process_elevated(),
+            ..Default::default()
+        }
+    }
+
+    /// Add the results of another deletion to this one.
+    pub fn merge(&mut self, other: DeleteReport) {
+        self.entries_deleted += other.entries_deleted;
+        self.bytes_freed += other.bytes_freed;
+        for (path, retries) in other.retries {
+            let entry = self.retries.entry(path).or_default();
+            *entry = (*entry).max(retries);
+        }
+        self.killed_processes.extend(other.killed_processes);
+        self.suspended_processes.extend(other.suspended_processes);
+        self.closed_handles.extend(other.closed_handles);
+        self.elapsed += other.elapsed;
+        self.elevated |= other.elevated;
+        // The elevated relaunch walks again what this process already walked
+        for excluded in other.excluded {
+            if !self.excluded.contains(&excluded) {
+                self.excluded.push(excluded);
+            }
+        }
+        self.quarantined.extend(other.quarantined);
+        self.tombstones.extend(other.tombstones);
+        self.mount_points.extend(other.mount_points);
+    }
+}
+
+impl fmt::Display for DeleteReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Deleted {} {} ({}) in {}ms{}.",
+            self.entries_deleted,
+            if self.entries_deleted == 1 {
+                "entry"
+            } else {
+                "entries"
+            },
+            format_bytes(self.bytes_freed),
+            self.elapsed.as_millis(),
+            if self.elevated { " as elevated" } else { "" }
+        )?;
+
+        if !self.retries.is_empty() {
+            let total_retries: u32 = self.retries.values().sum();
+            write!(
+                f,
+                " Used {} {} for {} {}.",
+                total_retries,
+                if total_retries == 1 {
+                    "retry"
+                } else {
+                    "retries"
+                },
+                self.retries.len(),
+                if self.retries.len() == 1 {
+                    "path"
+                } else {
+                    "paths"
+                }
+            )?;
+        }
+
+        if !self.killed_processes.is_empty() {
+            let killed = self
+                .killed_processes
+                .iter()
+                .filter(|k| k.outcome == KillOutcome::Killed)
+                .count();
+            write!(
+                f,
+                " Killed {} of {} {}.",
+                killed,
+                self.killed_processes.len(),
+                if self.killed_processes.len() == 1 {
+                    "process"
+                } else {
+                    "processes"
+                }
+            )?;
+        }
+
+        if !self.suspended_processes.is_empty() {
+            let suspended = self
+                .suspended_processes
+                .iter()
+                .filter(|s| s.outcome == SuspendOutcome::Suspended)
+                .count();
+            write!(
+                f,
+                " Suspended {} of {} {}.",
+                suspended,
+                self.suspended_processes.len(),
+                if self.suspended_processes.len() == 1 {
+                    "process"
+                } else {
+                    "processes"
+                }
+            )?;
+        }
+
+        if !self.closed_handles.is_empty() {
+            let closed = self
+                .closed_handles
+                .iter()
+                .filter(|c| c.outcome.is_ok())
+                .count();
+            write!(
+                f,
+                " Closed handles in {} of {} {}.",
+                closed,
+                self.closed_handles.len(),
+                if self.closed_handles.len() == 1 {
+                    "process"
+                } else {
+                    "processes"
+                }
+            )?;
+        }
+
+        if !self.excluded.is_empty() {
+            write!(
+                f,
+                " Kept {} excluded {}.",
+                self.excluded.len(),
+                if self.excluded.len() == 1 {
+                    "entry"
+                } else {
+                    "entries"
+                }
+            )?;
+        }
+
+        if !self.mount_points.is_empty() {
+            write!(
+                f,
+                " Skipped {} mount {}.",
+                self.mount_points.len(),
+                if self.mount_points.len() == 1 {
+                    "point"
+                } else {
+                    "points"
+                }
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// What deleting a file or directory would do, as reported by a dry run.
+#[derive(Debug, Default)]
+pub struct DryRunReport {
+    /// Files and directories that would be deleted or moved, with what would be done with them.
+    pub entries: Vec<PlannedEntry>,
+    /// Entries that are currently locked, with the processes holding them.
+    pub locked_entries: Vec<LockedEntry>,
+    /// Whether the deletion would need to relaunch as elevated.
+    pub needs_elevation: bool,
+    /// Entries that would be kept because they match an exclude glob.
+    pub excluded: Vec<PathBuf>,
+    /// Mount points that would be kept with their contents because they are on another volume.
+    pub mount_points: BTreeSet<PathBuf>,
+}
+
+impl DryRunReport {
+    /// The processes holding locks, without duplicates.
+    pub fn locking_processes(&self) -> Vec<&ProcessInfo> {
+        let mut processes: Vec<&ProcessInfo> = Vec::new();
+        for process_info in self.locked_entries.iter().flat_map(|e| &e.processes) {
+            if !processes
+                .iter()
+                .any(|p| p.process_id == process_info.process_id)
+            {
+                processes.push(process_info);
+            }
+        }
+        processes
+    }
+
+    /// Add the results of another dry run to this one.
+    pub fn merge(&mut self, other: DryRunReport) {
+        self.entries.extend(other.entries);
+        self.locked_entries.extend(other.locked_entries);
+        self.needs_elevation |= other.needs_elevation;
+        self.excluded.extend(other.excluded);
+        self.mount_points.extend(other.mount_points);
+    }
+}
+
+/// A file or directory a dry run found would be deleted or moved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedEntry {
+    pub path: PathBuf,
+    pub action: PlannedAction,
+}
+
+/// What would be done with an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlannedAction {
+    /// Deleted in place.
+    Delete,
+    /// Moved to the Recycle Bin, with `trash`.
+    Trash,
+    /// Moved into the quarantine, with `quarantine`.
+    Quarantine,
+    /// Renamed to a tombstone and deleted in the background, with `tombstone`.
+    Tombstone,
+}
+
+/// An entry that is currently locked.
+#[derive(Debug)]
+pub struct LockedEntry {
+    pub path: PathBuf,
+    pub processes: Vec<ProcessInfo>,
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,108 @@
+//! Restarting processes that were killed to release a lock
+//!
+//! The executable, command line, working directory and environment of a process are
+//! captured before it is killed, so it can be started again once the operation is done.
+
+use crate::lock_checker::{self, ProcessInfo};
+use std::ffi::OsStr;
+use std::os::windows::ffi::OsStrExt;
+use windows::Win32::Foundation::CloseHandle;
+use windows::Win32::System::Threading::{
+    CREATE_NEW_CONSOLE, CREATE_UNICODE_ENVIRONMENT, CreateProcessW, PROCESS_INFORMATION,
+    STARTUPINFOW,
+};
+use windows::core::{PCWSTR, PWSTR};
+
+/// Everything needed to start a killed process again
+#[derive(Debug, Clone)]
+pub struct LaunchInfo {
+    /// ID of the process that was killed
+    pub process_id: u32,
+    pub executable: String,
+    pub command_line: String,
+    pub current_directory: Option<String>,
+    /// Environment block of `NAME=value` strings, each null terminated, ending with a null
+    pub environment: Option<Vec<u16>>,
+}
+
+/// The result of restarting a killed process
+#[derive(Debug)]
+pub struct RestartOutcome {
+    pub launch_info: LaunchInfo,
+    /// The ID of the new process, or why it could not be started
+    pub result: Result<u32, String>,
+}
+
+/// Captures what is needed to restart a process. Must be called before the process is killed.
+pub fn capture_launch_info(process_info: &ProcessInfo) -> Option<LaunchInfo> {
+    let pid = process_info.process_id;
+    let executable = lock_checker::get_process_exe_path(pid)?;
+    let parameters = lock_checker::get_process_parameters(pid)?;
+
+    Some(LaunchInfo {
+        process_id: pid,
+        command_line: parameters
+            .command_line
+            .unwrap_or_else(|| format!("\"{}\"", executable)),
+        executable,
+        current_directory: parameters.current_directory,
+        environment: parameters.environment,
+    })
+}
+
+/// Starts a process again, returning the ID of the new process.
+pub fn restart_process(launch_info: &LaunchInfo) -> Result<u32, String> {
+    let executable = to_wide(&launch_info.executable);
+    let mut command_line = to_wide(&launch_info.command_line);
+    let current_directory = launch_info.current_directory.as_deref().map(to_wide);
+
+    let mut environment = launch_info.environment.clone();
+    if let Some(block) = environment.as_mut() {
+        // Make sure the block is terminated even if it was truncated when read
+        block.extend_from_slice(&[0, 0]);
+    }
+
+    let startup_info = STARTUPINFOW {
+        cb: std::mem::size_of::<STARTUPINFOW>() as u32,
+        ..Default::default()
+    };
+    let mut process_information = PROCESS_INFORMATION::default();
+
+    unsafe {
+        CreateProcessW(
+            PCWSTR(executable.as_ptr()),
+            Some(PWSTR(command_line.as_mut_ptr())),
+            None,
+            None,
+            false,
+            CREATE_NEW_CONSOLE | CREATE_UNICODE_ENVIRONMENT,
+            environment.as_ref().map(|e| e.as_ptr() as *const _),
+            current_directory
+                .as_ref()
+                .map_or(PCWSTR::null(), |d| PCWSTR(d.as_ptr())),
+            &startup_info,
+            &mut process_information,
+        )
+        .map_err(|e| e.to_string())?;
+
+        let _ = CloseHandle(process_information.hThread);
+        let _ = CloseHandle(process_information.hProcess);
+    }
+
+    Ok(process_information.dwProcessId)
+}
+
+/// Starts the given processes again.
+pub fn restart_processes(launch_infos: &[LaunchInfo]) -> Vec<RestartOutcome> {
+    launch_infos
+        .iter()
+        .map(|launch_info| RestartOutcome {
+            launch_info: launch_info.clone(),
+            result: restart_process(launch_info),
+        })
+        .collect()
+}
+
+fn to_wide(s: &str) -> Vec<u16> {
+    OsStr::new(s).encode_wide().chain(Some(0)).collect()
+}
@@ -0,0 +1,124 @@
+//! How many times to retry an operation, and how long to wait between attempts
+
+use crate::error_classifier::{ErrorClass, classify};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::time::Duration;
+
+/// How the delay between attempts grows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Backoff {
+    /// Wait the same delay before every retry.
+    #[default]
+    Fixed,
+    /// Wait the delay times the retry number.
+    Linear,
+    /// Double the delay with every retry.
+    Exponential,
+}
+
+/// How many times to retry an operation, and how long to wait before each retry
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// The number of retries. For example, five retries equals six total attempts.
+    pub max_retries: u32,
+    /// The delay before the first retry.
+    pub initial_delay: Duration,
+    /// How the delay grows with each retry.
+    pub backoff: Backoff,
+    /// The longest delay before a retry, however far the backoff has grown.
+    pub max_delay: Duration,
+    /// The fraction of each delay to randomly take off, from 0.0 to 1.0, so that
+    /// processes retrying together spread out.
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// A policy waiting the same delay before every retry.
+    pub fn fixed(max_retries: u32, delay: Duration) -> Self {
+        Self {
+            max_retries,
+            initial_delay: delay,
+            backoff: Backoff::Fixed,
+            max_delay: delay,
+            jitter: 0.0,
+        }
+    }
+
+    /// The delay before a retry, numbered from 1.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1);
+        let multiplier = match self.backoff {
+            Backoff::Fixed => 1,
+            Backoff::Linear => retry.max(1),
+            Backoff::Exponential => 2u32.saturating_pow(exponent),
+        };
+        let delay = self
+            .initial_delay
+            .saturating_mul(multiplier)
+            .min(self.max_delay.max(self.initial_delay));
+
+        if self.jitter > 0.0 {
+            delay.mul_f64(1.0 - self.jitter.clamp(0.0, 1.0) * random_fraction())
+        } else {
+            delay
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::fixed(10, Duration::from_millis(50))
+    }
+}
+
+/// Retry policies for each class of error, so that short lock blips can resolve fast
+/// while stubborn failures back off
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetryPolicies {
+    /// For entries held open by processes.
+    pub locked: RetryPolicy,
+    /// For entries the process lacks the rights to delete, which processes holding
+    /// running executables also cause.
+    pub permission: RetryPolicy,
+    /// For entries changed concurrently by something else.
+    pub racing: RetryPolicy,
+}
+
+impl RetryPolicies {
+    /// The same policy for every class of error.
+    pub fn uniform(policy: RetryPolicy) -> Self {
+        Self {
+            locked: policy.clone(),
+            permission: policy.clone(),
+            racing: policy,
+        }
+    }
+
+    /// The same fixed delay policy for every class of error.
+    pub fn fixed(max_retries: u32, delay: Duration) -> Self {
+        Self::uniform(RetryPolicy::fixed(max_retries, delay))
+    }
+
+    /// The policy for retrying after an error.
+    pub fn for_error(&self, error: &io::Error) -> &RetryPolicy {
+        match classify(error) {
+            ErrorClass::NeedsElevation => &self.permission,
+            ErrorClass::Racing => &self.racing,
+            ErrorClass::Locked | ErrorClass::Fatal => &self.locked,
+        }
+    }
+}
+
+/// A random number in [0, 1), good enough to spread out retries.
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
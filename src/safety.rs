@@ -0,0 +1,89 @@
+//! Refusing targets whose deletion would take the system or the user's files with it
+//!
+//! Like `rm --preserve-root`, but also covering the home directory, the working directory
+//! and the Windows directory, as fops kills the processes standing in its way.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Why a target is refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtectedPath {
+    /// The root of a drive or network share.
+    Root,
+    /// The user's home directory, or one of its ancestors.
+    Home,
+    /// The working directory, or one of its ancestors.
+    CurrentDirectory,
+    /// The Windows directory.
+    WindowsDirectory,
+}
+
+impl fmt::Display for ProtectedPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ProtectedPath::Root => "the root of a drive or share",
+            ProtectedPath::Home => "the home directory or one of its ancestors",
+            ProtectedPath::CurrentDirectory => "the current directory or one of its ancestors",
+            ProtectedPath::WindowsDirectory => "the Windows directory",
+        })
+    }
+}
+
+/// Check whether deleting a path is refused, and why. The final component of the path is
+/// not resolved, so a symlink to a protected directory can still be deleted.
+pub fn check_protected(path: &Path) -> Option<ProtectedPath> {
+    let target = resolve_parent(path);
+    if target.parent().is_none() {
+        return Some(ProtectedPath::Root);
+    }
+
+    let target = normalize(&target);
+    let contains_target = |directory: &Path| {
+        fs::canonicalize(directory)
+            .map(|directory| normalize(&directory).starts_with(&target))
+            .unwrap_or(false)
+    };
+
+    if let Some(home) = env::var_os("USERPROFILE")
+        && contains_target(Path::new(&home))
+    {
+        return Some(ProtectedPath::Home);
+    }
+
+    if let Ok(current_dir) = env::current_dir()
+        && contains_target(&current_dir)
+    {
+        return Some(ProtectedPath::CurrentDirectory);
+    }
+
+    if let Some(windows_dir) = env::var_os("SystemRoot")
+        && fs::canonicalize(&windows_dir).is_ok_and(|windows_dir| normalize(&windows_dir) == target)
+    {
+        return Some(ProtectedPath::WindowsDirectory);
+    }
+
+    None
+}
+
+/// Resolve the parent of a path, keeping its final component as it is.
+fn resolve_parent(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => fs::canonicalize(parent)
+            .map(|parent| parent.join(name))
+            .unwrap_or_else(|_| path.to_path_buf()),
+        _ => fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+/// Compare paths case-insensitively and without the verbatim prefix.
+fn normalize(path: &Path) -> PathBuf {
+    let lower = path.to_string_lossy().to_lowercase();
+    let stripped = match lower.strip_prefix(r"\\?\unc\") {
+        Some(unc) => format!(r"\\{}", unc),
+        None => lower.strip_prefix(r"\\?\").unwrap_or(&lower).to_string(),
+    };
+    PathBuf::from(stripped)
+}
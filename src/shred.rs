@@ -0,0 +1,93 @@
+//! Overwriting file contents before deleting them
+
+use std::fs::{self, File};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::os::windows::fs::OpenOptionsExt;
+use std::os::windows::io::AsRawHandle;
+use std::path::Path;
+use windows::Win32::Foundation::HANDLE;
+use windows::Win32::Security::Cryptography::{BCRYPT_USE_SYSTEM_PREFERRED_RNG, BCryptGenRandom};
+use windows::Win32::Storage::FileSystem::{
+    FILE_FLAG_BACKUP_SEMANTICS, GetVolumeInformationByHandleW,
+};
+
+/// Volume flag for file systems that can share blocks between files, such as ReFS.
+const FILE_SUPPORTS_BLOCK_REFCOUNTING: u32 = 0x0800_0000;
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// What to overwrite file contents with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ShredPattern {
+    /// Overwrite with zeros.
+    Zeros,
+    /// Overwrite with cryptographically random bytes.
+    #[default]
+    Random,
+}
+
+/// Overwrite the contents of a file opened for writing `passes` times, then truncate it to
+/// zero length. The file is flushed to disk after each pass.
+pub fn shred(file: &mut File, passes: u32, pattern: ShredPattern) -> io::Result<()> {
+    let length = file.metadata()?.len();
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+
+    for _ in 0..passes {
+        file.seek(SeekFrom::Start(0))?;
+        let mut remaining = length;
+        while remaining > 0 {
+            let chunk = remaining.min(BUFFER_SIZE as u64) as usize;
+            if pattern == ShredPattern::Random {
+                fill_random(&mut buffer[..chunk])?;
+            }
+            file.write_all(&buffer[..chunk])?;
+            remaining -= chunk as u64;
+        }
+        file.sync_all()?;
+    }
+
+    file.set_len(0)?;
+    file.sync_all()
+}
+
+/// Whether a file or directory is on a copy-on-write file system, where overwriting a file
+/// writes new blocks and may leave the old contents on disk.
+pub fn is_copy_on_write(path: &Path) -> bool {
+    let Ok(file) = fs::OpenOptions::new()
+        .access_mode(0)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS.0)
+        .open(path)
+    else {
+        return false;
+    };
+
+    let mut flags = 0u32;
+    let mut file_system_name = [0u16; 32];
+    let result = unsafe {
+        GetVolumeInformationByHandleW(
+            HANDLE(file.as_raw_handle()),
+            None,
+            None,
+            None,
+            Some(&mut flags),
+            Some(&mut file_system_name),
+        )
+    };
+    if result.is_err() {
+        return false;
+    }
+
+    let length = file_system_name
+        .iter()
+        .position(|&c| c == 0)
+        .unwrap_or(file_system_name.len());
+    let file_system_name = String::from_utf16_lossy(&file_system_name[..length]);
+
+    flags & FILE_SUPPORTS_BLOCK_REFCOUNTING != 0 || file_system_name.eq_ignore_ascii_case("ReFS")
+}
+
+fn fill_random(buffer: &mut [u8]) -> io::Result<()> {
+    unsafe { BCryptGenRandom(None, buffer, BCRYPT_USE_SYSTEM_PREFERRED_RNG) }
+        .ok()
+        .map_err(io::Error::other)
+}
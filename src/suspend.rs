@@ -0,0 +1,208 @@
+//! Suspending processes holding a lock, as an alternative to killing them
+//!
+//! Suspended processes are resumed when their [`SuspendedProcess`] is dropped. Processes
+//! are also resumed if fops is interrupted with Ctrl+C or panics, so a failed or
+//! cancelled operation never leaves them frozen.
+
+use crate::lock_checker::ProcessInfo;
+use crate::process::process_creation_time_from_handle;
+use std::sync::{Mutex, Once};
+use tracing::warn;
+use windows::Win32::Foundation::{CloseHandle, HANDLE, NTSTATUS};
+use windows::Win32::System::Console::SetConsoleCtrlHandler;
+use windows::Win32::System::Threading::{
+    GetCurrentProcessId, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_SUSPEND_RESUME,
+};
+use windows::core::BOOL;
+
+// Link to ntdll for NtSuspendProcess and NtResumeProcess
+#[link(name = "ntdll")]
+unsafe extern "system" {
+    fn NtSuspendProcess(process_handle: HANDLE) -> NTSTATUS;
+    fn NtResumeProcess(process_handle: HANDLE) -> NTSTATUS;
+}
+
+/// Handles of processes that are currently suspended, resumed on interruption.
+static SUSPENDED_HANDLES: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+static INSTALL_RESUME_GUARDS: Once = Once::new();
+
+/// A process suspended by fops. The process is resumed when this is dropped.
+#[derive(Debug)]
+pub struct SuspendedProcess {
+    process_info: ProcessInfo,
+    handle: usize,
+}
+
+/// The outcome of trying to suspend a process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SuspendOutcome {
+    Suspended,
+    /// The process exited and its ID was reused by another process after lock detection.
+    Skipped,
+    Failed(String),
+}
+
+/// A process that fops tried to suspend
+#[derive(Debug, Clone)]
+pub struct SuspendResult {
+    pub process_info: ProcessInfo,
+    pub outcome: SuspendOutcome,
+}
+
+impl SuspendedProcess {
+    pub fn process_info(&self) -> &ProcessInfo {
+        &self.process_info
+    }
+}
+
+impl Drop for SuspendedProcess {
+    fn drop(&mut self) {
+        // The handle is no longer registered if an interruption already resumed it
+        if unregister_handle(self.handle) {
+            let status = unsafe { NtResumeProcess(HANDLE(self.handle as _)) };
+            if let Err(e) = status.ok() {
+                warn!(
+                    "Failed to resume process {}: {}",
+                    self.process_info.process_id, e
+                );
+            }
+        }
+        unsafe {
+            let _ = CloseHandle(HANDLE(self.handle as _));
+        }
+    }
+}
+
+/// Suspends the specified processes, returning the ones that were suspended and the outcome
+/// for each of them.
+///
+/// Skips the current process and handles errors gracefully.
+pub fn suspend_processes(processes: &[ProcessInfo]) -> (Vec<SuspendedProcess>, Vec<SuspendResult>) {
+    INSTALL_RESUME_GUARDS.call_once(install_resume_guards);
+
+    let current_pid = unsafe { GetCurrentProcessId() };
+    let mut suspended = Vec::new();
+    let mut results = Vec::new();
+
+    for process_info in processes {
+        if process_info.process_id == current_pid {
+            continue;
+        }
+
+        let outcome = match suspend_process(process_info) {
+            Ok(Some(suspended_process)) => {
+                suspended.push(suspended_process);
+                SuspendOutcome::Suspended
+            }
+            Ok(None) => SuspendOutcome::Skipped,
+            Err(e) => {
+                warn!(
+                    "Failed to suspend process {}: {}",
+                    process_info.process_id, e
+                );
+                SuspendOutcome::Failed(e)
+            }
+        };
+        results.push(SuspendResult {
+            process_info: process_info.clone(),
+            outcome,
+        });
+    }
+
+    (suspended, results)
+}
+
+/// Suspends a single process, first verifying that the process ID still refers to the
+/// process that was detected. Returns `None` if the process was skipped.
+fn suspend_process(process_info: &ProcessInfo) -> Result<Option<SuspendedProcess>, String> {
+    let pid = process_info.process_id;
+
+    unsafe {
+        let handle = OpenProcess(
+            PROCESS_SUSPEND_RESUME | PROCESS_QUERY_LIMITED_INFORMATION,
+            false,
+            pid,
+        )
+        .map_err(|e| e.to_string())?;
+
+        if let Some(expected_start_time) = process_info.start_time
+            && process_creation_time_from_handle(handle) != Some(expected_start_time)
+        {
+            let _ = CloseHandle(handle);
+            warn!(
+                "Skipping process {}: the process exited and its ID was reused after lock detection",
+                pid
+            );
+            return Ok(None);
+        }
+
+        // Register before suspending, so an interruption in between cannot miss the process
+        register_handle(handle.0 as usize);
+        if let Err(e) = NtSuspendProcess(handle).ok() {
+            unregister_handle(handle.0 as usize);
+            let _ = CloseHandle(handle);
+            return Err(e.to_string());
+        }
+
+        Ok(Some(SuspendedProcess {
+            process_info: process_info.clone(),
+            handle: handle.0 as usize,
+        }))
+    }
+}
+
+fn register_handle(handle: usize) {
+    lock_suspended_handles().push(handle);
+}
+
+/// Returns true if the handle was registered.
+fn unregister_handle(handle: usize) -> bool {
+    let mut handles = lock_suspended_handles();
+    match handles.iter().position(|&h| h == handle) {
+        Some(index) => {
+            handles.swap_remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+fn lock_suspended_handles() -> std::sync::MutexGuard<'static, Vec<usize>> {
+    SUSPENDED_HANDLES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Resumes every suspended process. Used when fops is about to exit abnormally.
+fn resume_all() {
+    let handles = std::mem::take(&mut *lock_suspended_handles());
+    for handle in handles {
+        unsafe {
+            let _ = NtResumeProcess(HANDLE(handle as _));
+        }
+    }
+}
+
+fn install_resume_guards() {
+    unsafe {
+        if let Err(e) = SetConsoleCtrlHandler(Some(resume_on_interrupt), true) {
+            warn!(
+                "Failed to install Ctrl+C handler to resume suspended processes: {}",
+                e
+            );
+        }
+    }
+
+    // Release builds abort on panic without unwinding, so drop would not run
+    let previous_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        resume_all();
+        previous_hook(info);
+    }));
+}
+
+unsafe extern "system" fn resume_on_interrupt(_ctrl_type: u32) -> BOOL {
+    resume_all();
+    // Let the default handler terminate the process
+    BOOL(0)
+}
@@ -0,0 +1,141 @@
+//! Tombstones: targets renamed out of the way so they can be deleted in the background
+//!
+//! A tombstone stays next to its target, so the rename cannot cross volumes, and is named
+//! `.<name>.fops-tombstone-<pid>-<n>` so that leftovers from interrupted deletions can be found
+//! and cleaned up by later runs.
+
+use crate::config::{ForceOpsConfig, LockHolderAction};
+use crate::deadline::Deadline;
+use clap::ValueEnum;
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::os::windows::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+use windows::Win32::System::Threading::{
+    CREATE_NEW_PROCESS_GROUP, CREATE_NO_WINDOW, DETACHED_PROCESS,
+};
+
+const TOMBSTONE_MARKER: &str = ".fops-tombstone-";
+
+static NEXT_TOMBSTONE: AtomicU32 = AtomicU32::new(0);
+
+/// A new tombstone path next to `path`.
+pub fn tombstone_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(
+        "{}{}-{}",
+        TOMBSTONE_MARKER,
+        std::process::id(),
+        NEXT_TOMBSTONE.fetch_add(1, Ordering::Relaxed)
+    ));
+    path.with_file_name(name)
+}
+
+/// Whether a path is a tombstone, named exactly like `tombstone_path` names them.
+pub fn is_tombstone(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    let Some((target_name, id)) = name
+        .strip_prefix('.')
+        .and_then(|name| name.rsplit_once(TOMBSTONE_MARKER))
+    else {
+        return false;
+    };
+    let is_number = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+
+    !target_name.is_empty()
+        && id
+            .split_once('-')
+            .is_some_and(|(pid, n)| is_number(pid) && is_number(n))
+}
+
+/// Find tombstones left over from earlier deletions in the directories containing `paths`.
+pub fn find_leftover_tombstones(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let directories: BTreeSet<&Path> = paths.iter().filter_map(|p| p.parent()).collect();
+
+    let mut tombstones = Vec::new();
+    for directory in directories {
+        let Ok(entries) = fs::read_dir(directory) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if is_tombstone(&path) && !paths.contains(&path) {
+                tombstones.push(path);
+            }
+        }
+    }
+    tombstones
+}
+
+/// Delete tombstones in a detached fops process, which outlives this one. The process treats
+/// lock holders and retries as `config` does, and stops at `deadline`.
+pub fn delete_in_background(
+    tombstones: &[PathBuf],
+    config: &ForceOpsConfig,
+    deadline: Deadline,
+) -> io::Result<()> {
+    if tombstones.is_empty() {
+        return Ok(());
+    }
+
+    Command::new(std::env::current_exe()?)
+        .args(["delete", "--force", "--disable-elevate"])
+        .args(delete_args(config, deadline))
+        .arg("--")
+        .args(tombstones)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .creation_flags((DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP | CREATE_NO_WINDOW).0)
+        .spawn()?;
+    Ok(())
+}
+
+/// The `fops delete` options reproducing the lock holder, retry and timeout settings of `config`.
+fn delete_args(config: &ForceOpsConfig, deadline: Deadline) -> Vec<String> {
+    let policies = &config.retry_policy;
+    let policy = &policies.locked;
+    let mut args = vec![
+        format!("--retry-delay={}", policy.initial_delay.as_millis()),
+        format!("--max-retry-delay={}", policy.max_delay.as_millis()),
+        format!("--retry-jitter={}", policy.jitter),
+        format!("--locked-retries={}", policies.locked.max_retries),
+        format!("--permission-retries={}", policies.permission.max_retries),
+        format!("--racing-retries={}", policies.racing.max_retries),
+        format!("--jobs={}", config.jobs),
+    ];
+    if let Some(backoff) = policy.backoff.to_possible_value() {
+        args.push(format!("--backoff={}", backoff.get_name()));
+    }
+
+    match config.lock_holder_action {
+        LockHolderAction::Kill => {}
+        LockHolderAction::Suspend => args.push("--suspend".to_string()),
+        LockHolderAction::CloseHandles => args.push("--close-handles".to_string()),
+    }
+    if config.lock_holder_action == LockHolderAction::Suspend && config.close_handles_when_suspended
+    {
+        args.push("--close-handles".to_string());
+    }
+    if config.kill_process_tree {
+        args.push("--kill-tree".to_string());
+    }
+    if config.restart_killed {
+        args.push("--restart-killed".to_string());
+    }
+
+    if let Some(path_timeout) = config.path_timeout {
+        args.push(format!("--path-timeout={}ms", path_timeout.as_millis()));
+    }
+    if let Some(remaining) = deadline.remaining() {
+        args.push(format!("--timeout={}ms", remaining.as_millis()));
+    }
+    args
+}
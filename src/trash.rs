@@ -0,0 +1,80 @@
+//! Moving files and directories to the Recycle Bin
+
+use std::io;
+use std::os::windows::ffi::OsStrExt;
+use std::path::Path;
+use windows::Win32::Foundation::MAX_PATH;
+use windows::Win32::Storage::FileSystem::{GetDriveTypeW, GetVolumePathNameW};
+use windows::Win32::UI::Shell::{
+    FO_DELETE, FOF_ALLOWUNDO, FOF_NOCONFIRMATION, FOF_NOERRORUI, FOF_SILENT, SHFILEOPSTRUCTW,
+    SHFileOperationW, SHQUERYRBINFO, SHQueryRecycleBinW,
+};
+use windows::core::PCWSTR;
+
+/// Legacy shell error for when the source cannot be accessed.
+const DE_ACCESSDENIEDSRC: i32 = 0x78;
+const ERROR_ACCESS_DENIED: i32 = 5;
+const ERROR_CANCELLED: i32 = 1223;
+/// Drive type of fixed disks, the only ones the shell keeps a Recycle Bin on.
+const DRIVE_FIXED: u32 = 3;
+
+/// Move a file or directory to the Recycle Bin, without showing any UI.
+///
+/// On volumes without a Recycle Bin, such as network shares and removable drives, the shell
+/// would delete the entry permanently instead, so it is refused.
+pub fn move_to_trash(path: &Path) -> io::Result<()> {
+    // The shell requires absolute paths, in a list terminated by an extra null
+    let absolute_path = std::path::absolute(path)?;
+    if !has_recycle_bin(&absolute_path) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "there is no Recycle Bin on its volume to move it to",
+        ));
+    }
+    let from: Vec<u16> = absolute_path
+        .as_os_str()
+        .encode_wide()
+        .chain([0, 0])
+        .collect();
+
+    let mut operation = SHFILEOPSTRUCTW {
+        wFunc: FO_DELETE,
+        pFrom: PCWSTR(from.as_ptr()),
+        fFlags: (FOF_ALLOWUNDO | FOF_NOCONFIRMATION | FOF_NOERRORUI | FOF_SILENT).0 as u16,
+        ..Default::default()
+    };
+
+    let result = unsafe { SHFileOperationW(&mut operation) };
+    if result != 0 {
+        // Most codes are Win32 errors, but a few legacy ones are not
+        let code = match result {
+            DE_ACCESSDENIEDSRC => ERROR_ACCESS_DENIED,
+            code => code,
+        };
+        return Err(io::Error::from_raw_os_error(code));
+    }
+
+    if operation.fAnyOperationsAborted.as_bool() {
+        return Err(io::Error::from_raw_os_error(ERROR_CANCELLED));
+    }
+
+    Ok(())
+}
+
+/// Whether the volume of an absolute path has a Recycle Bin.
+fn has_recycle_bin(path: &Path) -> bool {
+    let path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut volume = [0u16; MAX_PATH as usize + 1];
+    if unsafe { GetVolumePathNameW(PCWSTR(path.as_ptr()), &mut volume) }.is_err() {
+        return false;
+    }
+    if unsafe { GetDriveTypeW(PCWSTR(volume.as_ptr())) } != DRIVE_FIXED {
+        return false;
+    }
+
+    let mut info = SHQUERYRBINFO {
+        cbSize: size_of::<SHQUERYRBINFO>() as u32,
+        ..Default::default()
+    };
+    unsafe { SHQueryRecycleBinW(PCWSTR(volume.as_ptr()), &mut info) }.is_ok()
+}
@@ -1,12 +1,42 @@
+use crate::safety::check_protected;
 use std::env;
+use std::ffi::OsStr;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io;
+use std::os::windows::ffi::OsStrExt;
+use std::os::windows::fs::{FileTypeExt, OpenOptionsExt};
+use std::os::windows::io::AsRawHandle;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+use tracing::warn;
+use windows::Win32::Foundation::HANDLE;
+use windows::Win32::Storage::FileSystem::{
+    FILE_FLAG_BACKUP_SEMANTICS, GetVolumeInformationByHandleW, GetVolumeNameForVolumeMountPointW,
+};
+use windows::core::PCWSTR;
 
 /// Combines a path with the current working directory and returns the absolute path.
+/// Symlinks are not resolved, so a path to a symlink stays a path to the symlink itself.
 pub fn combine_with_cwd_and_get_absolute_path(path: &str) -> PathBuf {
     let current_dir = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-    let combined = current_dir.join(path);
-    fs::canonicalize(&combined).unwrap_or(combined)
+    normalize_lexically(&current_dir.join(path))
+}
+
+/// Resolves `.` and `..` in a path without touching the file system, dropping trailing
+/// separators. `..` removes the preceding component as written, even if that is a symlink,
+/// and never goes above the root.
+pub fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
 }
 
 /// Checks if a path is a symbolic link (reparse point on Windows).
@@ -17,16 +47,142 @@ pub fn is_symlink(path: &Path) -> bool {
     }
 }
 
-/// Removes the read-only attribute from a file or directory if it's set.
-#[allow(clippy::permissions_set_readonly_false)]
-pub fn mark_as_not_readonly(path: &Path) -> std::io::Result<()> {
-    let metadata = fs::metadata(path)?;
-    let mut permissions = metadata.permissions();
+/// What a path is, without following symlinks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    /// A file, or a special file such as a Unix domain socket.
+    File,
+    Directory,
+    /// A symlink to a file, which may be dangling.
+    FileLink,
+    /// A symlink or junction to a directory, which may be dangling.
+    DirectoryLink,
+}
+
+impl EntryKind {
+    /// Classifies a path, or returns None if it does not exist.
+    pub fn of(path: &Path) -> Option<EntryKind> {
+        fs::symlink_metadata(path)
+            .ok()
+            .map(|metadata| EntryKind::from_file_type(metadata.file_type()))
+    }
+
+    pub fn from_file_type(file_type: fs::FileType) -> EntryKind {
+        if file_type.is_symlink_dir() {
+            EntryKind::DirectoryLink
+        } else if file_type.is_symlink_file() {
+            EntryKind::FileLink
+        } else if file_type.is_dir() {
+            EntryKind::Directory
+        } else {
+            EntryKind::File
+        }
+    }
+
+    /// Whether the entry is removed with `remove_dir` rather than `remove_file`.
+    pub fn is_directory(self) -> bool {
+        matches!(self, EntryKind::Directory | EntryKind::DirectoryLink)
+    }
+}
+
+/// Checks if a path exists, without following symlinks, so dangling symlinks exist.
+pub fn entry_exists(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok()
+}
+
+/// Checks if a symlink or junction to a directory can be followed: its target exists, is not
+/// protected, and is not a directory on the way to the link, which would loop. The directories on
+/// the way are compared once resolved, so links pointing at each other are caught too.
+pub fn can_follow_link(path: &Path) -> bool {
+    let Ok(target) = fs::canonicalize(path) else {
+        return false;
+    };
+    if let Some(reason) = check_protected(&target) {
+        warn!(
+            "Not following '{}' to '{}': it is {}",
+            path.display(),
+            target.display(),
+            reason
+        );
+        return false;
+    }
+
+    path.ancestors()
+        .skip(1)
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .all(|ancestor| {
+            fs::canonicalize(ancestor).is_ok_and(|visited| !visited.starts_with(&target))
+        })
+}
+
+/// Gets the serial number of the volume a file or directory is on, following symlinks.
+pub fn volume_serial_number(path: &Path) -> io::Result<u32> {
+    let file = fs::OpenOptions::new()
+        .access_mode(0)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS.0)
+        .open(path)?;
 
-    if permissions.readonly() {
-        permissions.set_readonly(false);
-        fs::set_permissions(path, permissions)?;
+    let mut serial_number = 0u32;
+    unsafe {
+        GetVolumeInformationByHandleW(
+            HANDLE(file.as_raw_handle()),
+            None,
+            Some(&mut serial_number),
+            None,
+            None,
+            None,
+        )
     }
+    .map_err(io::Error::other)?;
+    Ok(serial_number)
+}
+
+/// Checks if a directory is where another volume is mounted: a volume mount point, or a
+/// directory on a different volume than its parent, such as a bind mount in a container.
+/// Symlinks and junctions to directories are not mount points, as removing them does not
+/// touch what they point to.
+pub fn is_mount_point(path: &Path) -> bool {
+    if is_symlink(path) {
+        let mut mount_point: Vec<u16> = OsStr::new(path).encode_wide().collect();
+        if mount_point.last() != Some(&u16::from(b'\\')) {
+            mount_point.push(u16::from(b'\\'));
+        }
+        mount_point.push(0);
+
+        let mut volume_name = [0u16; 64];
+        return unsafe {
+            GetVolumeNameForVolumeMountPointW(PCWSTR(mount_point.as_ptr()), &mut volume_name)
+        }
+        .is_ok();
+    }
+
+    let Some(parent) = path.parent() else {
+        return false;
+    };
+    match (volume_serial_number(path), volume_serial_number(parent)) {
+        (Ok(serial_number), Ok(parent_serial_number)) => serial_number != parent_serial_number,
+        _ => false,
+    }
+}
+
+/// Parses a duration such as "500ms", "30s", "15m", "12h" or "7d". A bare number is in seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", value))?;
 
-    Ok(())
+    let seconds = match unit {
+        "ms" => return Ok(Duration::from_millis(number)),
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("invalid duration unit '{}' in '{}'", unit, value)),
+    };
+    Ok(Duration::from_secs(number * seconds))
 }
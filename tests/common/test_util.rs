@@ -2,7 +2,7 @@
 
 use std::fs;
 use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::time::{Duration, Instant};
 
@@ -22,6 +22,61 @@ pub fn hold_lock_on_file_using_powershell(file_path: &str) -> WrappedProcess {
     launch_powershell_with_command(&command, "")
 }
 
+/// Launch a PowerShell process in the specified directory that starts a child PowerShell
+/// process outside of it, writing the child's process ID to `child_pid_file`
+pub fn launch_process_tree_in_directory(
+    working_directory: &str,
+    child_pid_file: &str,
+) -> WrappedProcess {
+    let command = format!(
+        "$child = Start-Process powershell -ArgumentList '-NoProfile','-Command','sleep 10000' -WorkingDirectory $env:TEMP -WindowStyle Hidden -PassThru; Set-Content -Path '{}' -Value $child.Id",
+        child_pid_file
+    );
+    launch_powershell_with_command(&command, working_directory)
+}
+
+/// Check whether a process with the given ID is running, waiting briefly for it to exit
+pub fn is_process_running(pid: u32) -> bool {
+    let start_time = Instant::now();
+    while start_time.elapsed() < Duration::from_secs(5) {
+        let status = Command::new("powershell")
+            .args([
+                "-NoProfile",
+                "-Command",
+                &format!("Get-Process -Id {} -ErrorAction Stop", pid),
+            ])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .expect("Failed to start PowerShell process");
+
+        if !status.success() {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    true
+}
+
+/// Check whether every thread of the process with the given ID is suspended
+pub fn is_process_suspended(pid: u32) -> bool {
+    let status = Command::new("powershell")
+        .args([
+            "-NoProfile",
+            "-Command",
+            &format!(
+                "if ((Get-Process -Id {} -ErrorAction Stop).Threads | Where-Object {{ $_.WaitReason -ne 'Suspended' }}) {{ exit 1 }}",
+                pid
+            ),
+        ])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .expect("Failed to start PowerShell process");
+
+    status.success()
+}
+
 fn launch_powershell_with_command(command: &str, working_directory: &str) -> WrappedProcess {
     let full_command = format!(
         "$ErrorActionPreference='stop'; {}; echo 'process has been loaded'; sleep 10000",
@@ -69,6 +124,18 @@ fn wait_for_process_loaded_message(process: &mut std::process::Child) {
     panic!("Process exited before becoming ready");
 }
 
+/// Create a directory junction at `link` pointing to `target`, which needs no elevation
+pub fn create_junction(link: &Path, target: &Path) {
+    let status = Command::new("cmd")
+        .args(["/C", "mklink", "/J"])
+        .arg(link)
+        .arg(target)
+        .stdout(Stdio::null())
+        .status()
+        .expect("Failed to run mklink");
+    assert!(status.success(), "Failed to create junction");
+}
+
 /// Generate a unique temporary file path
 pub fn get_temporary_file_name() -> PathBuf {
     let temp_dir = std::env::temp_dir();
@@ -101,3 +168,45 @@ impl Drop for TempDirectory {
 pub fn create_temporary_directory(path: PathBuf) -> TempDirectory {
     TempDirectory::new(path)
 }
+
+/// A small virtual disk with an NTFS volume mounted at a directory, detached when dropped.
+/// Creating it needs elevation.
+pub struct MountedVirtualDisk {
+    file: PathBuf,
+}
+
+impl MountedVirtualDisk {
+    /// Create the virtual disk as `file` and mount its volume at the empty directory `mount_point`
+    pub fn mount(file: PathBuf, mount_point: &Path) -> Self {
+        run_diskpart(&format!(
+            "create vdisk file=\"{}\" maximum=16 type=expandable\nselect vdisk file=\"{}\"\nattach vdisk\ncreate partition primary\nformat fs=ntfs quick\nassign mount=\"{}\"\n",
+            file.display(),
+            file.display(),
+            mount_point.display()
+        ));
+        Self { file }
+    }
+}
+
+impl Drop for MountedVirtualDisk {
+    fn drop(&mut self) {
+        run_diskpart(&format!(
+            "select vdisk file=\"{}\"\ndetach vdisk\n",
+            self.file.display()
+        ));
+        let _ = fs::remove_file(&self.file);
+    }
+}
+
+fn run_diskpart(script: &str) {
+    let script_path = get_temporary_file_name().with_extension("txt");
+    fs::write(&script_path, script).expect("Failed to write diskpart script");
+    let status = Command::new("diskpart")
+        .arg("/s")
+        .arg(&script_path)
+        .stdout(Stdio::null())
+        .status()
+        .expect("Failed to run diskpart");
+    let _ = fs::remove_file(&script_path);
+    assert!(status.success(), "diskpart failed");
+}
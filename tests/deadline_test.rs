@@ -0,0 +1,45 @@
+use fops::deadline::Deadline;
+use std::time::Duration;
+
+#[test]
+fn no_deadline_never_expires() {
+    let deadline = Deadline::after(None);
+    assert_eq!(deadline, Deadline::NONE);
+    assert!(!deadline.is_expired());
+    assert_eq!(deadline.remaining(), None);
+    assert_eq!(
+        deadline.limit(Duration::from_secs(5)),
+        Duration::from_secs(5)
+    );
+}
+
+#[test]
+fn deadline_limits_waits_to_the_time_left() {
+    let deadline = Deadline::after(Some(Duration::from_secs(60)));
+    assert!(!deadline.is_expired());
+    assert_eq!(
+        deadline.limit(Duration::from_millis(50)),
+        Duration::from_millis(50)
+    );
+    assert!(deadline.limit(Duration::from_secs(600)) <= Duration::from_secs(60));
+
+    let expired = Deadline::after(Some(Duration::ZERO));
+    assert!(expired.is_expired());
+    assert_eq!(expired.limit(Duration::from_millis(50)), Duration::ZERO);
+}
+
+#[test]
+fn earliest_deadline_wins() {
+    let soon = Deadline::after(Some(Duration::ZERO));
+    let later = Deadline::after(Some(Duration::from_secs(60)));
+
+    assert_eq!(soon.earliest(later), soon);
+    assert_eq!(later.earliest(soon), soon);
+    assert_eq!(Deadline::NONE.earliest(later), later);
+    assert_eq!(later.earliest(Deadline::NONE), later);
+}
+
+#[test]
+fn huge_timeouts_mean_no_limit() {
+    assert_eq!(Deadline::after(Some(Duration::MAX)), Deadline::NONE);
+}
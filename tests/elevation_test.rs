@@ -0,0 +1,170 @@
+mod common;
+
+use common::test_util::{create_temporary_directory, get_temporary_file_name};
+use fops::elevation::ElevatedResult;
+use fops::handles::CloseHandlesResult;
+use fops::lock_checker::ProcessInfo;
+use fops::process::{KillOutcome, KillResult};
+use fops::report::DeleteReport;
+use fops::restart::LaunchInfo;
+use fops::suspend::{SuspendOutcome, SuspendResult};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[test]
+fn elevated_result_round_trips_through_its_file() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    let result_file = temp_folder_path.join("result");
+
+    let environment: Vec<u16> = "A=1\0B=\t\\\0\0".encode_utf16().collect();
+    let result = ElevatedResult {
+        killed_processes: vec![
+            LaunchInfo {
+                process_id: 42,
+                executable: "C:\\Program Files\\app.exe".to_string(),
+                command_line: "\"C:\\Program Files\\app.exe\" --name\t\"a\\tb\"\n".to_string(),
+                current_directory: Some("C:\\work".to_string()),
+                environment: Some(environment.clone()),
+            },
+            LaunchInfo {
+                process_id: 7,
+                executable: "C:\\tool.exe".to_string(),
+                command_line: "tool".to_string(),
+                current_directory: None,
+                environment: None,
+            },
+        ],
+        report: DeleteReport::default(),
+    };
+    result.write(&result_file).unwrap();
+
+    let read = ElevatedResult::read(&result_file).unwrap();
+    assert_eq!(read.killed_processes.len(), 2);
+    let first = &read.killed_processes[0];
+    assert_eq!(first.process_id, 42);
+    assert_eq!(first.executable, result.killed_processes[0].executable);
+    assert_eq!(first.command_line, result.killed_processes[0].command_line);
+    assert_eq!(first.current_directory.as_deref(), Some("C:\\work"));
+    assert_eq!(first.environment.as_ref(), Some(&environment));
+    let second = &read.killed_processes[1];
+    assert_eq!(second.current_directory, None);
+    assert_eq!(second.environment, None);
+}
+
+#[test]
+fn elevated_result_round_trips_the_delete_report() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    let result_file = temp_folder_path.join("result");
+
+    let path = PathBuf::from("C:\\work\\a\tb");
+    let result = ElevatedResult {
+        killed_processes: Vec::new(),
+        report: DeleteReport {
+            entries_deleted: 12,
+            bytes_freed: 3456,
+            retries: BTreeMap::from([(path.clone(), 2)]),
+            killed_processes: vec![
+                KillResult {
+                    process_info: ProcessInfo {
+                        process_id: 42,
+                        start_time: Some(133_000_000_000_000_000),
+                        executable_name: Some("app.exe".to_string()),
+                        application_name: None,
+                    },
+                    outcome: KillOutcome::Killed,
+                },
+                KillResult {
+                    process_info: ProcessInfo {
+                        process_id: 7,
+                        start_time: None,
+                        executable_name: None,
+                        application_name: Some("Tool".to_string()),
+                    },
+                    outcome: KillOutcome::Failed("Access is denied.\tTwice".to_string()),
+                },
+            ],
+            suspended_processes: vec![SuspendResult {
+                process_info: ProcessInfo {
+                    process_id: 9,
+                    start_time: Some(133_000_000_000_000_001),
+                    executable_name: Some("editor.exe".to_string()),
+                    application_name: None,
+                },
+                outcome: SuspendOutcome::Suspended,
+            }],
+            closed_handles: vec![
+                CloseHandlesResult {
+                    process_info: ProcessInfo {
+                        process_id: 9,
+                        start_time: Some(133_000_000_000_000_001),
+                        executable_name: Some("editor.exe".to_string()),
+                        application_name: None,
+                    },
+                    outcome: Ok(3),
+                },
+                CloseHandlesResult {
+                    process_info: ProcessInfo {
+                        process_id: 11,
+                        start_time: None,
+                        executable_name: None,
+                        application_name: None,
+                    },
+                    outcome: Err("Access is denied.".to_string()),
+                },
+            ],
+            elapsed: Duration::from_millis(789),
+            elevated: true,
+            excluded: vec![path.join("kept")],
+            quarantined: BTreeMap::from([(path.clone(), "20260101-000000-1".to_string())]),
+            tombstones: vec![path.with_extension("fops-tombstone")],
+            mount_points: BTreeSet::from([path.join("mounted")]),
+        },
+    };
+    result.write(&result_file).unwrap();
+
+    let read = ElevatedResult::read(&result_file).unwrap().report;
+    let report = &result.report;
+    assert_eq!(read.entries_deleted, report.entries_deleted);
+    assert_eq!(read.bytes_freed, report.bytes_freed);
+    assert_eq!(read.retries, report.retries);
+    assert_eq!(read.elapsed, report.elapsed);
+    assert!(read.elevated);
+    assert_eq!(read.excluded, report.excluded);
+    assert_eq!(read.quarantined, report.quarantined);
+    assert_eq!(read.tombstones, report.tombstones);
+    assert_eq!(read.mount_points, report.mount_points);
+    assert_eq!(read.killed_processes.len(), 2);
+    let killed = &read.killed_processes[0];
+    assert_eq!(killed.process_info.process_id, 42);
+    assert_eq!(
+        killed.process_info.start_time,
+        Some(133_000_000_000_000_000)
+    );
+    assert_eq!(
+        killed.process_info.executable_name.as_deref(),
+        Some("app.exe")
+    );
+    assert_eq!(killed.process_info.application_name, None);
+    assert_eq!(killed.outcome, KillOutcome::Killed);
+    let failed = &read.killed_processes[1];
+    assert_eq!(failed.process_info.start_time, None);
+    assert_eq!(failed.outcome, report.killed_processes[1].outcome);
+    assert_eq!(read.suspended_processes.len(), 1);
+    let suspended = &read.suspended_processes[0];
+    assert_eq!(suspended.process_info.process_id, 9);
+    assert_eq!(
+        suspended.process_info.executable_name.as_deref(),
+        Some("editor.exe")
+    );
+    assert_eq!(suspended.outcome, SuspendOutcome::Suspended);
+    assert_eq!(read.closed_handles.len(), 2);
+    assert_eq!(read.closed_handles[0].process_info.process_id, 9);
+    assert_eq!(read.closed_handles[0].outcome, Ok(3));
+    assert_eq!(
+        read.closed_handles[1].outcome,
+        report.closed_handles[1].outcome
+    );
+}
@@ -0,0 +1,29 @@
+use fops::error_classifier::{ErrorClass, classify};
+use std::io;
+
+#[test]
+fn sharing_violation_is_locked() {
+    let error = io::Error::from_raw_os_error(32);
+    assert_eq!(classify(&error), ErrorClass::Locked);
+    assert!(classify(&error).is_retryable());
+}
+
+#[test]
+fn directory_not_empty_is_racing() {
+    let error = io::Error::from_raw_os_error(145);
+    assert_eq!(classify(&error), ErrorClass::Racing);
+    assert!(classify(&error).is_retryable());
+}
+
+#[test]
+fn access_denied_needs_elevation() {
+    let error = io::Error::from_raw_os_error(5);
+    assert_eq!(classify(&error), ErrorClass::NeedsElevation);
+    assert!(!classify(&error).is_retryable());
+}
+
+#[test]
+fn write_protect_is_fatal() {
+    let error = io::Error::from_raw_os_error(19);
+    assert_eq!(classify(&error), ErrorClass::Fatal);
+}
@@ -4,12 +4,25 @@
 mod common;
 
 use common::test_util::{
-    create_temporary_directory, get_temporary_file_name, hold_lock_on_file_using_powershell,
-    launch_process_in_directory,
+    MountedVirtualDisk, create_junction, create_temporary_directory, get_temporary_file_name,
+    hold_lock_on_file_using_powershell, is_process_running, is_process_suspended,
+    launch_process_in_directory, launch_process_tree_in_directory,
 };
-use fops::config::ForceOpsConfig;
-use fops::deleter::FileAndDirectoryDeleter;
+use fops::config::{ForceOpsConfig, LockHolderAction};
+use fops::deleter::{DeleteError, FileAndDirectoryDeleter};
+use fops::filter::PathFilter;
+use fops::process::KillOutcome;
+use fops::report::{PlannedAction, PlannedEntry};
+use fops::retry::RetryPolicies;
+use fops::safety::ProtectedPath;
+use fops::shred::ShredPattern;
+use fops::suspend::SuspendOutcome;
+use fops::{lock_checker, process, tombstone, utils};
 use std::fs;
+use std::os::windows::fs::OpenOptionsExt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
 
 #[test]
 fn deleting_directory_open_in_powershell_working_directory() {
@@ -21,9 +34,9 @@ fn deleting_directory_open_in_powershell_working_directory() {
 
     // With no retries, should fail
     let config_no_retries = ForceOpsConfig {
-        max_retries: 0,
-        retry_delay_ms: 50,
+        retry_policy: RetryPolicies::fixed(0, Duration::from_millis(50)),
         disable_elevate: true,
+        ..ForceOpsConfig::default()
     };
     let deleter = FileAndDirectoryDeleter::new(config_no_retries);
     let result = deleter.delete_directory(&temp_folder_path);
@@ -31,9 +44,9 @@ fn deleting_directory_open_in_powershell_working_directory() {
 
     // With retries, should succeed after killing the process
     let config_with_retries = ForceOpsConfig {
-        max_retries: 10,
-        retry_delay_ms: 50,
+        retry_policy: RetryPolicies::fixed(10, Duration::from_millis(50)),
         disable_elevate: true,
+        ..ForceOpsConfig::default()
     };
     let deleter = FileAndDirectoryDeleter::new(config_with_retries);
     let result = deleter.delete_directory(&temp_folder_path);
@@ -56,9 +69,9 @@ fn deleting_readonly_directory_open_in_powershell_working_directory() {
 
     // With no retries, should fail
     let config_no_retries = ForceOpsConfig {
-        max_retries: 0,
-        retry_delay_ms: 50,
+        retry_policy: RetryPolicies::fixed(0, Duration::from_millis(50)),
         disable_elevate: true,
+        ..ForceOpsConfig::default()
     };
     let deleter = FileAndDirectoryDeleter::new(config_no_retries);
     let result = deleter.delete_directory(&temp_folder_path);
@@ -66,9 +79,9 @@ fn deleting_readonly_directory_open_in_powershell_working_directory() {
 
     // With retries, should succeed after killing the process
     let config_with_retries = ForceOpsConfig {
-        max_retries: 10,
-        retry_delay_ms: 50,
+        retry_policy: RetryPolicies::fixed(10, Duration::from_millis(50)),
         disable_elevate: true,
+        ..ForceOpsConfig::default()
     };
     let deleter = FileAndDirectoryDeleter::new(config_with_retries);
     let result = deleter.delete_directory(&temp_folder_path);
@@ -85,9 +98,9 @@ fn deleting_file_open_by_powershell() {
 
     // With no retries, should fail
     let config_no_retries = ForceOpsConfig {
-        max_retries: 0,
-        retry_delay_ms: 50,
+        retry_policy: RetryPolicies::fixed(0, Duration::from_millis(50)),
         disable_elevate: true,
+        ..ForceOpsConfig::default()
     };
     let deleter = FileAndDirectoryDeleter::new(config_no_retries);
     let result = deleter.delete_file(&temp_file_path);
@@ -95,9 +108,9 @@ fn deleting_file_open_by_powershell() {
 
     // With retries, should succeed after killing the process
     let config_with_retries = ForceOpsConfig {
-        max_retries: 10,
-        retry_delay_ms: 50,
+        retry_policy: RetryPolicies::fixed(10, Duration::from_millis(50)),
         disable_elevate: true,
+        ..ForceOpsConfig::default()
     };
     let deleter = FileAndDirectoryDeleter::new(config_with_retries);
     let result = deleter.delete_file(&temp_file_path);
@@ -119,9 +132,9 @@ fn deleting_readonly_file_open_by_powershell() {
 
     // With no retries, should fail
     let config_no_retries = ForceOpsConfig {
-        max_retries: 0,
-        retry_delay_ms: 50,
+        retry_policy: RetryPolicies::fixed(0, Duration::from_millis(50)),
         disable_elevate: true,
+        ..ForceOpsConfig::default()
     };
     let deleter = FileAndDirectoryDeleter::new(config_no_retries);
     let result = deleter.delete_file(&temp_file_path);
@@ -129,12 +142,1067 @@ fn deleting_readonly_file_open_by_powershell() {
 
     // With retries, should succeed after killing the process
     let config_with_retries = ForceOpsConfig {
-        max_retries: 10,
-        retry_delay_ms: 50,
+        retry_policy: RetryPolicies::fixed(10, Duration::from_millis(50)),
         disable_elevate: true,
+        ..ForceOpsConfig::default()
     };
     let deleter = FileAndDirectoryDeleter::new(config_with_retries);
     let result = deleter.delete_file(&temp_file_path);
     assert!(result.is_ok(), "Should succeed with retries: {:?}", result);
     assert!(!temp_file_path.exists(), "File should be deleted");
 }
+
+#[test]
+fn deleting_directory_with_kill_tree_kills_child_processes() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    let temp_path_str = temp_folder_path.to_string_lossy().to_string();
+    let child_pid_file = get_temporary_file_name();
+
+    let _process =
+        launch_process_tree_in_directory(&temp_path_str, &child_pid_file.to_string_lossy());
+    let child_pid: u32 = fs::read_to_string(&child_pid_file)
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap();
+    let _ = fs::remove_file(&child_pid_file);
+
+    let config = ForceOpsConfig {
+        kill_process_tree: true,
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let result = deleter.delete_directory(&temp_folder_path);
+    assert!(result.is_ok(), "Should succeed with retries: {:?}", result);
+    assert!(!temp_folder_path.exists(), "Directory should be deleted");
+    assert!(
+        !is_process_running(child_pid),
+        "Child process {} should be killed",
+        child_pid
+    );
+}
+
+#[test]
+fn deleting_file_with_restart_killed_restarts_process() {
+    let temp_file_path = get_temporary_file_name();
+    let temp_path_str = temp_file_path.to_string_lossy().to_string();
+
+    let process = hold_lock_on_file_using_powershell(&temp_path_str);
+    let pid = process.process.id();
+
+    let config = ForceOpsConfig {
+        restart_killed: true,
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let result = deleter.delete_file(&temp_file_path);
+    assert!(result.is_ok(), "Should succeed with retries: {:?}", result);
+    assert!(!temp_file_path.exists(), "File should be deleted");
+
+    let outcomes = deleter.restart_killed_processes();
+    assert_eq!(outcomes.len(), 1, "Should restart the killed process");
+    assert_eq!(outcomes[0].launch_info.process_id, pid);
+    let new_pid = *outcomes[0]
+        .result
+        .as_ref()
+        .expect("Should restart successfully");
+    assert!(is_process_running(new_pid), "Restarted process should run");
+
+    // The restarted process holds the file again
+    process::kill_processes(&lock_checker::get_locks(&temp_file_path).unwrap_or_default());
+    let _ = fs::remove_file(&temp_file_path);
+}
+
+#[test]
+fn deleting_file_with_suspend_keeps_handles_open_and_resumes_process_afterwards() {
+    let temp_file_path = get_temporary_file_name();
+    let temp_path_str = temp_file_path.to_string_lossy().to_string();
+
+    let process = hold_lock_on_file_using_powershell(&temp_path_str);
+    let pid = process.process.id();
+
+    let config = ForceOpsConfig {
+        retry_policy: RetryPolicies::fixed(2, Duration::from_millis(50)),
+        lock_holder_action: LockHolderAction::Suspend,
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let result = deleter.delete_file(&temp_file_path);
+    assert!(
+        matches!(result, Err(DeleteError::LockedByProcesses { .. })),
+        "Should stay locked by the suspended process: {:?}",
+        result
+    );
+    assert!(temp_file_path.exists(), "File should not be deleted");
+    assert!(
+        is_process_suspended(pid),
+        "Process should stay suspended until resumed"
+    );
+
+    deleter.resume_suspended_processes();
+    assert!(!is_process_suspended(pid), "Process should be resumed");
+    assert!(is_process_running(pid), "Process should still be running");
+
+    process::kill_processes(&lock_checker::get_locks(&temp_file_path).unwrap_or_default());
+    let _ = fs::remove_file(&temp_file_path);
+}
+
+#[test]
+fn deleting_file_with_suspend_and_close_handles_fallback_reports_both() {
+    let temp_file_path = get_temporary_file_name();
+    let temp_path_str = temp_file_path.to_string_lossy().to_string();
+
+    let process = hold_lock_on_file_using_powershell(&temp_path_str);
+    let pid = process.process.id();
+
+    let config = ForceOpsConfig {
+        retry_policy: RetryPolicies::fixed(3, Duration::from_millis(50)),
+        lock_holder_action: LockHolderAction::Suspend,
+        close_handles_when_suspended: true,
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let report = deleter.delete_file(&temp_file_path).unwrap();
+    assert!(!temp_file_path.exists(), "File should be deleted");
+    assert!(
+        report
+            .suspended_processes
+            .iter()
+            .any(|s| s.process_info.process_id == pid && s.outcome == SuspendOutcome::Suspended),
+        "Should report our PowerShell process as suspended: {:?}",
+        report.suspended_processes
+    );
+    assert!(
+        report
+            .closed_handles
+            .iter()
+            .any(|c| c.process_info.process_id == pid && c.outcome.is_ok()),
+        "Should report the handles closed in our PowerShell process: {:?}",
+        report.closed_handles
+    );
+    assert!(
+        is_process_suspended(pid),
+        "Process should stay suspended until resumed"
+    );
+
+    deleter.resume_suspended_processes();
+    assert!(!is_process_suspended(pid), "Process should be resumed");
+    assert!(is_process_running(pid), "Process should still be running");
+}
+
+#[test]
+fn deleting_file_with_close_handles_keeps_process_running() {
+    let temp_file_path = get_temporary_file_name();
+    let temp_path_str = temp_file_path.to_string_lossy().to_string();
+
+    let process = hold_lock_on_file_using_powershell(&temp_path_str);
+    let pid = process.process.id();
+
+    let config = ForceOpsConfig {
+        lock_holder_action: LockHolderAction::CloseHandles,
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let report = deleter.delete_file(&temp_file_path).unwrap();
+    assert!(!temp_file_path.exists(), "File should be deleted");
+    assert!(
+        is_process_running(pid),
+        "Process should not be killed when its handle can be closed"
+    );
+    assert!(
+        report
+            .closed_handles
+            .iter()
+            .any(|c| c.process_info.process_id == pid && c.outcome.is_ok()),
+        "Should report the handles closed in our PowerShell process: {:?}",
+        report.closed_handles
+    );
+}
+
+#[test]
+fn dry_run_reports_locked_file_without_deleting() {
+    let temp_file_path = get_temporary_file_name();
+    let temp_path_str = temp_file_path.to_string_lossy().to_string();
+
+    let process = hold_lock_on_file_using_powershell(&temp_path_str);
+    let pid = process.process.id();
+
+    let deleter = FileAndDirectoryDeleter::new(ForceOpsConfig::default());
+    let report = deleter.dry_run(&temp_file_path, false).unwrap();
+
+    assert_eq!(
+        report.entries,
+        vec![PlannedEntry {
+            path: temp_file_path.clone(),
+            action: PlannedAction::Delete,
+        }]
+    );
+    assert_eq!(report.locked_entries.len(), 1, "File should be locked");
+    assert!(
+        report
+            .locking_processes()
+            .iter()
+            .any(|p| p.process_id == pid),
+        "Should find our PowerShell process (pid: {})",
+        pid
+    );
+    assert!(temp_file_path.exists(), "File should not be deleted");
+    assert!(is_process_running(pid), "Process should not be killed");
+}
+
+#[test]
+fn dry_run_with_trash_reports_move_without_moving() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    fs::write(temp_folder_path.join("file"), "content").unwrap();
+
+    let config = ForceOpsConfig {
+        trash: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let report = deleter.dry_run(&temp_folder_path, false).unwrap();
+
+    assert_eq!(
+        report.entries,
+        vec![PlannedEntry {
+            path: temp_folder_path.clone(),
+            action: PlannedAction::Trash,
+        }],
+        "Only the target should be moved, as a whole"
+    );
+    assert!(
+        temp_folder_path.join("file").exists(),
+        "Directory should not be moved"
+    );
+}
+
+#[test]
+fn deleting_directory_reports_what_was_deleted() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    fs::create_dir_all(temp_folder_path.join("subdir")).unwrap();
+    fs::write(temp_folder_path.join("file1"), [0u8; 100]).unwrap();
+    fs::write(temp_folder_path.join("subdir").join("file2"), [0u8; 50]).unwrap();
+
+    let deleter = FileAndDirectoryDeleter::new(ForceOpsConfig::default());
+    let report = deleter.delete_directory(&temp_folder_path).unwrap();
+
+    assert_eq!(
+        report.entries_deleted, 4,
+        "Should count files and directories"
+    );
+    assert_eq!(report.bytes_freed, 150, "Should count the size of files");
+    assert!(report.retries.is_empty(), "Should not need retries");
+    assert!(
+        report.killed_processes.is_empty(),
+        "Should not kill processes"
+    );
+}
+
+#[test]
+fn deleting_locked_file_reports_killed_process() {
+    let temp_file_path = get_temporary_file_name();
+    let temp_path_str = temp_file_path.to_string_lossy().to_string();
+
+    let process = hold_lock_on_file_using_powershell(&temp_path_str);
+    let pid = process.process.id();
+
+    let config = ForceOpsConfig {
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let report = deleter.delete_file(&temp_file_path).unwrap();
+
+    assert_eq!(report.entries_deleted, 1);
+    assert!(
+        report.retries.get(&temp_file_path).is_some_and(|&r| r >= 1),
+        "Should record retries for the file: {:?}",
+        report.retries
+    );
+    assert!(
+        report
+            .killed_processes
+            .iter()
+            .any(|k| k.process_info.process_id == pid && k.outcome == KillOutcome::Killed),
+        "Should report our PowerShell process as killed: {:?}",
+        report.killed_processes
+    );
+}
+
+#[test]
+fn deleting_locked_file_without_retries_reports_locking_process() {
+    let temp_file_path = get_temporary_file_name();
+    let temp_path_str = temp_file_path.to_string_lossy().to_string();
+
+    let process = hold_lock_on_file_using_powershell(&temp_path_str);
+    let pid = process.process.id();
+
+    let config = ForceOpsConfig {
+        retry_policy: RetryPolicies::fixed(0, Duration::from_millis(50)),
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let error = deleter.delete_file(&temp_file_path).unwrap_err();
+
+    match &error {
+        DeleteError::LockedByProcesses {
+            path, processes, ..
+        } => {
+            assert_eq!(path, &temp_file_path);
+            assert!(
+                processes.iter().any(|p| p.process_id == pid),
+                "Should find our PowerShell process (pid: {}): {:?}",
+                pid,
+                processes
+            );
+        }
+        _ => panic!("Expected LockedByProcesses, got {:?}", error),
+    }
+    assert!(error.needs_elevation());
+}
+
+#[test]
+fn deleting_locked_file_stops_retrying_at_timeout() {
+    let temp_file_path = get_temporary_file_name();
+
+    // The test process never kills itself, so every retry fails
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .share_mode(0)
+        .open(&temp_file_path)
+        .unwrap();
+    let pid = std::process::id();
+
+    let config = ForceOpsConfig {
+        retry_policy: RetryPolicies::fixed(1000, Duration::from_millis(50)),
+        path_timeout: Some(Duration::from_millis(500)),
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let start = std::time::Instant::now();
+    let error = deleter.delete_file(&temp_file_path).unwrap_err();
+
+    assert!(
+        start.elapsed() < Duration::from_secs(10),
+        "Should stop retrying soon after the timeout, took {:?}",
+        start.elapsed()
+    );
+    match &error {
+        DeleteError::TimedOut { path, processes } => {
+            assert_eq!(path, &temp_file_path);
+            assert!(
+                processes.iter().any(|p| p.process_id == pid),
+                "Should report the test process (pid: {}): {:?}",
+                pid,
+                processes
+            );
+        }
+        _ => panic!("Expected TimedOut, got {:?}", error),
+    }
+    assert!(temp_file_path.exists());
+
+    drop(file);
+    let _ = fs::remove_file(&temp_file_path);
+}
+
+#[test]
+fn deleting_after_timeout_returns_timed_out() {
+    let temp_file_path = get_temporary_file_name();
+    fs::write(&temp_file_path, "content").unwrap();
+
+    let config = ForceOpsConfig {
+        timeout: Some(Duration::ZERO),
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let error = deleter
+        .delete_file_or_directory(&temp_file_path, false)
+        .unwrap_err();
+
+    assert!(matches!(error, DeleteError::TimedOut { .. }));
+    assert!(temp_file_path.exists());
+    fs::remove_file(&temp_file_path).unwrap();
+}
+
+#[test]
+fn deleting_current_directory_is_refused_unless_preserve_root_is_disabled() {
+    let current_dir = std::env::current_dir().unwrap();
+
+    let deleter = FileAndDirectoryDeleter::new(ForceOpsConfig::default());
+    let error = deleter
+        .delete_file_or_directory(&current_dir, false)
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        DeleteError::Protected {
+            reason: ProtectedPath::CurrentDirectory,
+            ..
+        }
+    ));
+    assert!(
+        !error.to_string().contains("--no-preserve-root"),
+        "The library error should not mention command line flags: {}",
+        error
+    );
+    assert!(matches!(
+        deleter.dry_run(&current_dir, false).unwrap_err(),
+        DeleteError::Protected { .. }
+    ));
+    assert!(current_dir.exists());
+
+    let config = ForceOpsConfig {
+        preserve_root: false,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    assert!(deleter.dry_run(&current_dir, false).is_ok());
+}
+
+#[test]
+fn deleting_missing_path_returns_not_found() {
+    let temp_file_path = get_temporary_file_name();
+
+    let deleter = FileAndDirectoryDeleter::new(ForceOpsConfig::default());
+    let error = deleter
+        .delete_file_or_directory(&temp_file_path, false)
+        .unwrap_err();
+
+    assert!(matches!(error, DeleteError::NotFound { .. }));
+    assert_eq!(error.path(), temp_file_path);
+}
+
+#[test]
+fn deleting_all_stops_at_first_failure() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    let missing = temp_folder_path.join("missing");
+    let file = temp_folder_path.join("file");
+    fs::write(&file, "").unwrap();
+
+    let deleter = FileAndDirectoryDeleter::new(ForceOpsConfig::default());
+    let outcome = deleter.delete_all(&[missing.clone(), file.clone()], false);
+
+    assert!(
+        file.exists(),
+        "Targets after the failure should not be attempted"
+    );
+    assert_eq!(outcome.failures.len(), 1);
+    assert_eq!(outcome.failures[0].path(), missing);
+    assert_eq!(outcome.not_attempted, vec![file]);
+}
+
+#[test]
+fn deleting_all_with_keep_going_attempts_every_target() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    let first = temp_folder_path.join("first");
+    let second = temp_folder_path.join("second");
+    let missing = temp_folder_path.join("missing");
+    fs::write(&first, "content").unwrap();
+    fs::create_dir(&second).unwrap();
+
+    let config = ForceOpsConfig {
+        keep_going: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let outcome = deleter.delete_all(&[first.clone(), missing.clone(), second.clone()], false);
+
+    assert!(!first.exists());
+    assert!(!second.exists());
+    assert_eq!(outcome.report.entries_deleted, 2);
+    assert_eq!(outcome.report.bytes_freed, 7);
+    assert!(matches!(
+        outcome.failures.as_slice(),
+        [DeleteError::NotFound { path }] if *path == missing
+    ));
+    assert!(outcome.not_attempted.is_empty());
+}
+
+#[test]
+fn deleting_directory_keeps_excluded_entries_and_their_ancestors() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    fs::create_dir_all(temp_folder_path.join("keep").join("nested")).unwrap();
+    fs::create_dir_all(temp_folder_path.join("remove")).unwrap();
+    fs::write(
+        temp_folder_path
+            .join("keep")
+            .join("nested")
+            .join(".gitkeep"),
+        "",
+    )
+    .unwrap();
+    fs::write(temp_folder_path.join("keep").join("file"), "").unwrap();
+    fs::write(temp_folder_path.join("remove").join("file"), "").unwrap();
+
+    let config = ForceOpsConfig {
+        filter: PathFilter::new(&[], &[".gitkeep".to_string()]).unwrap(),
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let report = deleter.delete_directory(&temp_folder_path).unwrap();
+
+    let gitkeep = temp_folder_path
+        .join("keep")
+        .join("nested")
+        .join(".gitkeep");
+    assert!(gitkeep.exists(), "Excluded file should be kept");
+    assert!(
+        !temp_folder_path.join("keep").join("file").exists(),
+        "Sibling of excluded file should be deleted"
+    );
+    assert!(
+        !temp_folder_path.join("remove").exists(),
+        "Directory without excluded entries should be deleted"
+    );
+    assert_eq!(report.excluded, vec![gitkeep]);
+    assert_eq!(report.entries_deleted, 3);
+}
+
+#[test]
+fn deleting_directory_only_deletes_included_entries() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    fs::create_dir_all(temp_folder_path.join("sub")).unwrap();
+    fs::write(temp_folder_path.join("a.log"), "").unwrap();
+    fs::write(temp_folder_path.join("sub").join("b.log"), "").unwrap();
+    fs::write(temp_folder_path.join("sub").join("c.txt"), "").unwrap();
+
+    let config = ForceOpsConfig {
+        filter: PathFilter::new(&["*.log".to_string()], &[]).unwrap(),
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let report = deleter.delete_directory(&temp_folder_path).unwrap();
+
+    assert!(!temp_folder_path.join("a.log").exists());
+    assert!(!temp_folder_path.join("sub").join("b.log").exists());
+    assert!(temp_folder_path.join("sub").join("c.txt").exists());
+    assert!(temp_folder_path.exists(), "Root is not included");
+    assert_eq!(report.entries_deleted, 2);
+}
+
+#[test]
+fn deleting_directory_on_one_file_system_removes_junctions_but_not_their_targets() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    let target_path = get_temporary_file_name();
+    let _target_dir = create_temporary_directory(target_path.clone());
+    fs::write(target_path.join("file"), "content").unwrap();
+    fs::create_dir_all(temp_folder_path.join("nested")).unwrap();
+    fs::write(temp_folder_path.join("nested").join("file"), "").unwrap();
+    let junction_path = temp_folder_path.join("nested").join("junction");
+    create_junction(&junction_path, &target_path);
+
+    // Junctions are not mount points, as removing them does not touch their target
+    assert!(!utils::is_mount_point(&temp_folder_path.join("nested")));
+    assert!(!utils::is_mount_point(&junction_path));
+
+    let config = ForceOpsConfig {
+        one_file_system: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let report = deleter.delete_directory(&temp_folder_path).unwrap();
+
+    assert!(!temp_folder_path.exists());
+    assert!(target_path.join("file").exists());
+    assert!(report.mount_points.is_empty());
+    assert_eq!(report.entries_deleted, 4);
+}
+
+#[test]
+#[ignore = "mounting a virtual disk needs elevation"]
+fn deleting_directory_on_one_file_system_keeps_mount_points_and_their_holders() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    fs::write(temp_folder_path.join("file"), "").unwrap();
+    let mount_point = temp_folder_path.join("nested").join("mounted");
+    fs::create_dir_all(&mount_point).unwrap();
+    let _disk = MountedVirtualDisk::mount(
+        get_temporary_file_name().with_extension("vhdx"),
+        &mount_point,
+    );
+    fs::create_dir(mount_point.join("inner")).unwrap();
+    fs::write(mount_point.join("inner").join("file"), "content").unwrap();
+    assert!(utils::is_mount_point(&mount_point));
+
+    let process = launch_process_in_directory(mount_point.join("inner").to_str().unwrap());
+    let pid = process.process.id();
+
+    let config = ForceOpsConfig {
+        one_file_system: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let report = deleter.delete_directory(&temp_folder_path).unwrap();
+
+    assert!(!temp_folder_path.join("file").exists());
+    assert!(
+        mount_point.join("inner").join("file").exists(),
+        "Contents of the mounted volume should be kept"
+    );
+    assert_eq!(
+        report.mount_points.iter().collect::<Vec<_>>(),
+        vec![&mount_point]
+    );
+    assert!(
+        report.killed_processes.is_empty(),
+        "Processes inside the mounted volume should be left alone"
+    );
+    assert!(is_process_running(pid), "Process should not be killed");
+}
+
+#[test]
+fn deleting_directory_removes_readonly_entries() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    let sub = temp_folder_path.join("sub");
+    fs::create_dir(&sub).unwrap();
+    fs::write(sub.join("file"), "content").unwrap();
+    for path in [sub.join("file"), sub.clone()] {
+        let mut perms = fs::metadata(&path).unwrap().permissions();
+        perms.set_readonly(true);
+        fs::set_permissions(&path, perms).unwrap();
+    }
+
+    let deleter = FileAndDirectoryDeleter::new(ForceOpsConfig::default());
+    let report = deleter.delete_directory(&temp_folder_path).unwrap();
+
+    assert!(!temp_folder_path.exists());
+    assert_eq!(report.entries_deleted, 3);
+    assert_eq!(report.bytes_freed, 7);
+}
+
+#[test]
+fn deleting_dangling_junction_removes_the_link() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    let target_path = temp_folder_path.join("target");
+    fs::create_dir(&target_path).unwrap();
+    let junction_path = temp_folder_path.join("junction");
+    create_junction(&junction_path, &target_path);
+    fs::remove_dir(&target_path).unwrap();
+
+    let deleter = FileAndDirectoryDeleter::new(ForceOpsConfig::default());
+    deleter
+        .delete_file_or_directory(&junction_path, false)
+        .unwrap();
+
+    assert!(!utils::entry_exists(&junction_path));
+}
+
+#[test]
+fn deleting_directory_with_follow_links_deletes_contents_of_link_targets() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    let target_path = get_temporary_file_name();
+    let _target_dir = create_temporary_directory(target_path.clone());
+    fs::create_dir(target_path.join("sub")).unwrap();
+    fs::write(target_path.join("sub").join("file"), "content").unwrap();
+    create_junction(&temp_folder_path.join("junction"), &target_path);
+
+    let config = ForceOpsConfig {
+        follow_links: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    deleter.delete_directory(&temp_folder_path).unwrap();
+
+    assert!(!temp_folder_path.exists());
+    assert!(target_path.is_dir(), "Link target itself should be kept");
+    assert_eq!(fs::read_dir(&target_path).unwrap().count(), 0);
+}
+
+#[test]
+fn deleting_directory_with_follow_links_does_not_follow_links_to_ancestors() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    fs::create_dir(temp_folder_path.join("sub")).unwrap();
+    create_junction(
+        &temp_folder_path.join("sub").join("loop"),
+        &temp_folder_path,
+    );
+
+    let config = ForceOpsConfig {
+        follow_links: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    deleter.delete_directory(&temp_folder_path).unwrap();
+
+    assert!(!temp_folder_path.exists());
+}
+
+#[test]
+fn deleting_directory_with_follow_links_does_not_loop_through_sibling_links() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    fs::create_dir(temp_folder_path.join("a")).unwrap();
+    fs::create_dir(temp_folder_path.join("b")).unwrap();
+    create_junction(
+        &temp_folder_path.join("a").join("link"),
+        &temp_folder_path.join("b"),
+    );
+    create_junction(
+        &temp_folder_path.join("b").join("link"),
+        &temp_folder_path.join("a"),
+    );
+
+    let config = ForceOpsConfig {
+        follow_links: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    deleter.delete_directory(&temp_folder_path).unwrap();
+
+    assert!(!temp_folder_path.exists());
+}
+
+#[test]
+fn directories_swapped_for_junctions_while_deleting_keep_junction_targets() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    let outside_path = get_temporary_file_name();
+    let _outside_dir = create_temporary_directory(outside_path.clone());
+    let moved_path = get_temporary_file_name();
+    let _moved_dir = create_temporary_directory(moved_path.clone());
+    for i in 0..20 {
+        let subdirectory = temp_folder_path.join(format!("sub{}", i));
+        fs::create_dir(&subdirectory).unwrap();
+        for j in 0..50 {
+            fs::write(subdirectory.join(format!("file{}", j)), "content").unwrap();
+        }
+    }
+    for j in 0..50 {
+        fs::write(outside_path.join(format!("file{}", j)), "keep").unwrap();
+    }
+
+    let config = ForceOpsConfig {
+        retry_policy: RetryPolicies::fixed(2, Duration::from_millis(50)),
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let done = AtomicBool::new(false);
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            let _ = deleter.delete_directory(&temp_folder_path);
+            done.store(true, Ordering::SeqCst);
+        });
+        // Swap directories the deleter may have listed, or opened, for junctions outside
+        for i in 0..20 {
+            if done.load(Ordering::SeqCst) {
+                break;
+            }
+            let subdirectory = temp_folder_path.join(format!("sub{}", i));
+            if fs::rename(&subdirectory, moved_path.join(format!("sub{}", i))).is_ok() {
+                create_junction(&subdirectory, &outside_path);
+            }
+        }
+    });
+
+    for j in 0..50 {
+        assert_eq!(
+            fs::read_to_string(outside_path.join(format!("file{}", j))).unwrap(),
+            "keep",
+            "Junction target should be untouched"
+        );
+    }
+}
+
+#[test]
+fn links_to_protected_directories_are_not_followed() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    let link_path = temp_folder_path.join("link");
+    create_junction(&link_path, &std::env::current_dir().unwrap());
+
+    assert!(!utils::can_follow_link(&link_path));
+}
+
+#[test]
+fn deleting_directory_contents_only_keeps_root() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    fs::create_dir_all(temp_folder_path.join("sub")).unwrap();
+    fs::write(temp_folder_path.join("sub").join("file"), "").unwrap();
+    let locked_file = temp_folder_path.join("locked");
+    let _process = hold_lock_on_file_using_powershell(&locked_file.to_string_lossy());
+
+    let config = ForceOpsConfig {
+        contents_only: true,
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let report = deleter.delete_directory(&temp_folder_path).unwrap();
+
+    assert!(temp_folder_path.is_dir(), "Root should be kept");
+    assert_eq!(
+        fs::read_dir(&temp_folder_path).unwrap().count(),
+        0,
+        "Root should be empty"
+    );
+    assert_eq!(report.entries_deleted, 3);
+}
+
+#[test]
+fn moving_locked_file_to_trash_kills_process() {
+    let temp_file_path = get_temporary_file_name();
+    let temp_path_str = temp_file_path.to_string_lossy().to_string();
+
+    let process = hold_lock_on_file_using_powershell(&temp_path_str);
+    let pid = process.process.id();
+
+    let config = ForceOpsConfig {
+        trash: true,
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let report = deleter
+        .delete_file_or_directory(&temp_file_path, false)
+        .unwrap();
+
+    assert!(
+        !temp_file_path.exists(),
+        "File should be moved to the trash"
+    );
+    assert_eq!(report.entries_deleted, 1);
+    assert!(
+        report
+            .killed_processes
+            .iter()
+            .any(|k| k.process_info.process_id == pid),
+        "Should kill our PowerShell process: {:?}",
+        report.killed_processes
+    );
+}
+
+#[test]
+fn moving_directory_to_tombstone_frees_its_name() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    fs::write(temp_folder_path.join("file"), "").unwrap();
+    let _process = launch_process_in_directory(&temp_folder_path.to_string_lossy());
+
+    let config = ForceOpsConfig {
+        tombstone: true,
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let report = deleter
+        .delete_file_or_directory(&temp_folder_path, false)
+        .unwrap();
+
+    assert!(!temp_folder_path.exists(), "Name should be free");
+    assert_eq!(report.tombstones.len(), 1);
+    let tombstone_path = &report.tombstones[0];
+    assert!(tombstone::is_tombstone(tombstone_path));
+    assert_eq!(tombstone_path.parent(), temp_folder_path.parent());
+    assert_eq!(
+        tombstone::find_leftover_tombstones(std::slice::from_ref(&temp_folder_path)),
+        vec![tombstone_path.clone()]
+    );
+
+    fs::remove_dir_all(tombstone_path).unwrap();
+}
+
+#[test]
+fn only_entries_named_like_tombstones_are_leftover_tombstones() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    let target = temp_folder_path.join("a");
+    let tombstone_path = temp_folder_path.join(".a.fops-tombstone-123-0");
+    fs::write(&tombstone_path, "").unwrap();
+    for name in [
+        "notes.fops-tombstone-backup",
+        ".notes.fops-tombstone-backup",
+        ".a.fops-tombstone-123",
+        ".a.fops-tombstone-123-0-copy",
+        "a.fops-tombstone-123-0",
+        "..fops-tombstone-123-0",
+    ] {
+        fs::write(temp_folder_path.join(name), "").unwrap();
+        assert!(
+            !tombstone::is_tombstone(&temp_folder_path.join(name)),
+            "{}",
+            name
+        );
+    }
+
+    assert_eq!(
+        tombstone::find_leftover_tombstones(&[target]),
+        vec![tombstone_path]
+    );
+}
+
+#[test]
+fn shredding_file_truncates_it_before_deleting() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    let secret = temp_folder_path.join("secret");
+    fs::write(&secret, "password").unwrap();
+    // A hard link keeps the contents reachable after the file is deleted
+    let link = temp_folder_path.join("link");
+    fs::hard_link(&secret, &link).unwrap();
+
+    let config = ForceOpsConfig {
+        shred_passes: 2,
+        shred_pattern: ShredPattern::Zeros,
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    deleter.delete_file(&secret).unwrap();
+
+    assert!(!secret.exists(), "File should be deleted");
+    assert_eq!(fs::read(&link).unwrap(), Vec::<u8>::new());
+}
+
+#[test]
+fn shredding_readonly_file_truncates_it_before_deleting() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    let secret = temp_folder_path.join("secret");
+    fs::write(&secret, "password").unwrap();
+    let link = temp_folder_path.join("link");
+    fs::hard_link(&secret, &link).unwrap();
+    let mut perms = fs::metadata(&secret).unwrap().permissions();
+    perms.set_readonly(true);
+    fs::set_permissions(&secret, perms).unwrap();
+
+    let config = ForceOpsConfig {
+        shred_passes: 1,
+        shred_pattern: ShredPattern::Zeros,
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    deleter.delete_file(&secret).unwrap();
+
+    assert!(!secret.exists(), "File should be deleted");
+    assert_eq!(fs::read(&link).unwrap(), Vec::<u8>::new());
+}
+
+#[test]
+fn deleting_directory_with_locked_file_retries_only_that_file() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    for i in 0..20 {
+        let subdirectory = temp_folder_path.join(format!("sub{}", i));
+        fs::create_dir_all(&subdirectory).unwrap();
+        for j in 0..5 {
+            fs::write(subdirectory.join(format!("file{}", j)), "").unwrap();
+        }
+    }
+    let locked_file = temp_folder_path.join("sub7").join("locked");
+    let process = hold_lock_on_file_using_powershell(&locked_file.to_string_lossy());
+    let pid = process.process.id();
+
+    let config = ForceOpsConfig {
+        disable_elevate: true,
+        jobs: 4,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let report = deleter.delete_directory(&temp_folder_path).unwrap();
+
+    assert!(!temp_folder_path.exists(), "Directory should be deleted");
+    assert_eq!(
+        report.retries.keys().collect::<Vec<_>>(),
+        vec![&locked_file],
+        "Only the locked file should need retries"
+    );
+    assert!(
+        report
+            .killed_processes
+            .iter()
+            .any(|k| k.process_info.process_id == pid),
+        "Should kill our PowerShell process: {:?}",
+        report.killed_processes
+    );
+}
+
+#[test]
+fn deleting_directory_with_locked_file_counts_every_entry_once() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    fs::create_dir_all(temp_folder_path.join("sub")).unwrap();
+    fs::write(temp_folder_path.join("file"), [0u8; 100]).unwrap();
+    fs::write(temp_folder_path.join("sub").join("file"), [0u8; 50]).unwrap();
+    let locked_file = temp_folder_path.join("sub").join("locked");
+    let _process = hold_lock_on_file_using_powershell(&locked_file.to_string_lossy());
+
+    let config = ForceOpsConfig {
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let report = deleter.delete_directory(&temp_folder_path).unwrap();
+
+    assert!(!temp_folder_path.exists(), "Directory should be deleted");
+    assert_eq!(
+        report.entries_deleted, 5,
+        "Should count the entries deleted by both the fast and the slow path"
+    );
+    assert_eq!(report.bytes_freed, 150);
+}
+
+#[test]
+fn busy_resources_are_not_reported_as_mount_points() {
+    // ERROR_BUSY, which is not specific to mount points
+    let error = DeleteError::from_io(
+        &get_temporary_file_name(),
+        std::io::Error::from_raw_os_error(170),
+    );
+
+    assert!(matches!(error, DeleteError::Busy { .. }), "{:?}", error);
+    assert!(!error.to_string().contains("Mount point"), "{}", error);
+}
+
+#[test]
+fn deleting_directory_with_nested_locked_files_retries_only_those_files() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    let nested_path = temp_folder_path.join("a").join("b");
+    fs::create_dir_all(&nested_path).unwrap();
+    fs::create_dir_all(temp_folder_path.join("c")).unwrap();
+    fs::write(nested_path.join("file"), "").unwrap();
+    fs::write(temp_folder_path.join("c").join("file"), "").unwrap();
+    let locked_files = [nested_path.join("locked"), temp_folder_path.join("locked")];
+    let _processes: Vec<_> = locked_files
+        .iter()
+        .map(|path| hold_lock_on_file_using_powershell(&path.to_string_lossy()))
+        .collect();
+
+    let config = ForceOpsConfig {
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let report = deleter.delete_directory(&temp_folder_path).unwrap();
+
+    assert!(!temp_folder_path.exists(), "Directory should be deleted");
+    let mut expected_retries: Vec<_> = locked_files.iter().collect();
+    expected_retries.sort();
+    assert_eq!(
+        report.retries.keys().collect::<Vec<_>>(),
+        expected_retries,
+        "Only the locked files should need retries"
+    );
+}
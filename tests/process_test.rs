@@ -0,0 +1,42 @@
+//! Tests for process termination
+
+mod common;
+
+use common::test_util::{
+    create_temporary_directory, get_temporary_file_name, is_process_running,
+    launch_process_in_directory,
+};
+use fops::lock_checker;
+use fops::process;
+
+#[test]
+fn kill_skips_process_with_different_start_time() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    let temp_path_str = temp_folder_path.to_string_lossy().to_string();
+
+    let process = launch_process_in_directory(&temp_path_str);
+    let pid = process.process.id();
+
+    let mut locks = lock_checker::get_locks(&temp_folder_path).unwrap();
+    let our_process = locks
+        .iter_mut()
+        .find(|p| p.process_id == pid)
+        .expect("Should find our PowerShell process");
+    let start_time = our_process.start_time.expect("Should have a start time");
+
+    // Pretend the process ID was reused by a process started later
+    our_process.start_time = Some(start_time + 1);
+    process::kill_processes(std::slice::from_ref(our_process));
+    assert!(
+        is_process_running(pid),
+        "Process with a different start time should not be killed"
+    );
+
+    our_process.start_time = Some(start_time);
+    process::kill_processes(std::slice::from_ref(our_process));
+    assert!(
+        !is_process_running(pid),
+        "Process with a matching start time should be killed"
+    );
+}
@@ -4,11 +4,14 @@
 mod common;
 
 use common::test_util::{
-    create_temporary_directory, get_temporary_file_name, launch_process_in_directory,
+    create_junction, create_temporary_directory, get_temporary_file_name,
+    launch_process_in_directory,
 };
 use std::fs::{self, File};
+use std::os::windows::fs::OpenOptionsExt;
 use std::path::PathBuf;
-use std::process::Command;
+use std::process::{Command, Stdio};
+use windows::Win32::Storage::FileSystem::{FILE_SHARE_READ, FILE_SHARE_WRITE};
 
 fn get_forceops_exe() -> PathBuf {
     // Get the path to the built executable
@@ -45,6 +48,37 @@ fn delete_multiple_files() {
     let _ = fs::remove_dir_all(&temp_dir);
 }
 
+#[test]
+fn delete_dry_run_does_not_delete() {
+    let temp_dir = get_temporary_file_name();
+    fs::create_dir_all(&temp_dir).unwrap();
+    let file = temp_dir.join("file");
+    File::create(&file).unwrap();
+
+    let output = Command::new(get_forceops_exe())
+        .args(["delete", "--dry-run", &temp_dir.to_string_lossy()])
+        .output()
+        .expect("Failed to run forceops");
+
+    assert!(output.status.success(), "Dry run should succeed");
+    assert!(file.exists(), "File should not be deleted");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(&format!("Would delete '{}'", file.display())),
+        "Should list the file: {}",
+        stdout
+    );
+    assert!(
+        stdout.contains("Would delete 2 entries, 0 locked"),
+        "Should summarize the dry run: {}",
+        stdout
+    );
+
+    // Cleanup
+    let _ = fs::remove_dir_all(&temp_dir);
+}
+
 #[test]
 fn delete_non_existing_file_throws_message() {
     let output = Command::new(get_forceops_exe())
@@ -131,6 +165,120 @@ fn retry_delay_and_max_retries_work() {
     );
 }
 
+#[test]
+fn timeout_exits_with_distinct_code() {
+    let temp_dir = get_temporary_file_name();
+    let _temp_dir_guard = create_temporary_directory(temp_dir.clone());
+    let temp_path_str = temp_dir.to_string_lossy().to_string();
+
+    // fops never kills itself, so a file it holds open as its output fails every retry
+    let output_file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .share_mode((FILE_SHARE_READ | FILE_SHARE_WRITE).0)
+        .open(temp_dir.join("output"))
+        .unwrap();
+    let mut command = Command::new(get_forceops_exe());
+    command
+        .args([
+            "delete",
+            &temp_path_str,
+            "--max-retries",
+            "1000",
+            "--timeout",
+            "1s",
+            "--disable-elevate",
+        ])
+        .stdout(output_file)
+        .stderr(Stdio::piped());
+    let child = command.spawn().expect("Failed to run forceops");
+    // Close the handle of the test process, so only fops holds the file
+    drop(command);
+    let output = child.wait_with_output().unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(3), "Output: {}", stderr);
+    assert!(stderr.contains("Timed out"), "Output: {}", stderr);
+    assert!(temp_dir.exists(), "Directory should not be deleted");
+}
+
+#[test]
+fn delete_with_keep_going_deletes_remaining_targets_and_reports_every_failure() {
+    let temp_dir = get_temporary_file_name();
+    let _temp_dir_guard = create_temporary_directory(temp_dir.clone());
+    let file = temp_dir.join("file");
+    File::create(&file).unwrap();
+
+    let output = Command::new(get_forceops_exe())
+        .args([
+            "delete",
+            "missing1",
+            "file",
+            "missing2",
+            "--keep-going",
+            "--disable-elevate",
+        ])
+        .current_dir(&temp_dir)
+        .output()
+        .expect("Failed to run forceops");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "Output: {}", stderr);
+    assert!(!file.exists(), "file should be deleted");
+    assert!(
+        stderr.contains("Failed to delete 2 targets"),
+        "Output: {}",
+        stderr
+    );
+    assert!(stderr.contains("missing1"), "Output: {}", stderr);
+    assert!(stderr.contains("missing2"), "Output: {}", stderr);
+}
+
+#[test]
+fn delete_current_directory_is_refused() {
+    let temp_dir = get_temporary_file_name();
+    let _temp_dir_guard = create_temporary_directory(temp_dir.clone());
+
+    let output = Command::new(get_forceops_exe())
+        .args(["delete", ".", "--disable-elevate"])
+        .current_dir(&temp_dir)
+        .output()
+        .expect("Failed to run forceops");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "forceops should fail");
+    assert!(stderr.contains("--no-preserve-root"), "Output: {}", stderr);
+    assert!(temp_dir.exists(), "Directory should not be deleted");
+}
+
+#[test]
+fn delete_junction_removes_only_the_link() {
+    let temp_dir = get_temporary_file_name();
+    let _temp_dir_guard = create_temporary_directory(temp_dir.clone());
+    let target = temp_dir.join("target");
+    fs::create_dir(&target).unwrap();
+    File::create(target.join("file")).unwrap();
+    let junction = temp_dir.join("junction");
+    create_junction(&junction, &target);
+
+    let output = Command::new(get_forceops_exe())
+        .args(["delete", "junction", "--disable-elevate"])
+        .current_dir(&temp_dir)
+        .output()
+        .expect("Failed to run forceops");
+
+    assert!(
+        output.status.success(),
+        "forceops should succeed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        fs::symlink_metadata(&junction).is_err(),
+        "Link should be deleted"
+    );
+    assert!(target.join("file").exists(), "Target should be untouched");
+}
+
 #[test]
 fn help_command_works() {
     let output = Command::new(get_forceops_exe())
@@ -217,3 +365,23 @@ fn list_command_output_format() {
         stdout
     );
 }
+
+#[test]
+fn shredding_with_zero_passes_is_refused() {
+    let temp_dir = get_temporary_file_name();
+    let _temp_dir_guard = create_temporary_directory(temp_dir.clone());
+    let file = temp_dir.join("file");
+    fs::write(&file, "secret").unwrap();
+
+    let output = Command::new(get_forceops_exe())
+        .args(["delete", "--shred", "--shred-passes", "0"])
+        .arg(&file)
+        .output()
+        .expect("Failed to run forceops");
+
+    assert!(!output.status.success(), "forceops should fail");
+    assert!(
+        file.exists(),
+        "File should not be deleted without shredding"
+    );
+}
@@ -0,0 +1,181 @@
+mod common;
+
+use common::test_util::{
+    MountedVirtualDisk, create_junction, create_temporary_directory, get_temporary_file_name,
+};
+use fops::config::ForceOpsConfig;
+use fops::deleter::FileAndDirectoryDeleter;
+use fops::quarantine::{self, QuarantineError};
+use std::fs;
+use std::os::windows::fs::symlink_file;
+
+#[test]
+fn quarantined_directory_can_be_restored() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    fs::create_dir_all(temp_folder_path.join("sub")).unwrap();
+    fs::write(temp_folder_path.join("sub").join("file"), "contents").unwrap();
+
+    let config = ForceOpsConfig {
+        quarantine: true,
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let report = deleter
+        .delete_file_or_directory(&temp_folder_path, false)
+        .unwrap();
+
+    assert!(
+        !temp_folder_path.exists(),
+        "Directory should be quarantined"
+    );
+    let id = report.quarantined.get(&temp_folder_path).unwrap();
+
+    let manifest = quarantine::list()
+        .unwrap()
+        .into_iter()
+        .find(|m| &m.id == id)
+        .expect("Quarantined item should be listed");
+    assert_eq!(manifest.original_path, temp_folder_path);
+    assert_eq!(manifest.hashes.len(), 1, "Should hash the single file");
+
+    quarantine::restore(id).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(temp_folder_path.join("sub").join("file")).unwrap(),
+        "contents"
+    );
+    assert!(
+        quarantine::restore(id).is_err(),
+        "Restored item should be removed from the quarantine"
+    );
+}
+
+#[test]
+fn restoring_id_outside_the_quarantine_is_refused() {
+    for id in ["..\\x", "..", "C:\\Windows", "1-2-3\\..\\..", ""] {
+        assert!(
+            matches!(quarantine::restore(id), Err(QuarantineError::InvalidId(_))),
+            "'{}' should be refused",
+            id
+        );
+    }
+}
+
+#[test]
+fn quarantined_directory_with_junction_can_be_restored() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    let target_path = get_temporary_file_name();
+    let _target_dir = create_temporary_directory(target_path.clone());
+    fs::write(target_path.join("file"), "outside").unwrap();
+    fs::write(temp_folder_path.join("file"), "contents").unwrap();
+    create_junction(&temp_folder_path.join("junction"), &target_path);
+    create_junction(
+        &temp_folder_path.join("dangling"),
+        &get_temporary_file_name(),
+    );
+
+    let config = ForceOpsConfig {
+        quarantine: true,
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let report = deleter
+        .delete_file_or_directory(&temp_folder_path, false)
+        .unwrap();
+    let id = report.quarantined.get(&temp_folder_path).unwrap();
+
+    let manifest = quarantine::list()
+        .unwrap()
+        .into_iter()
+        .find(|m| &m.id == id)
+        .expect("Quarantined item should be listed");
+    assert_eq!(
+        manifest.hashes.len(),
+        3,
+        "Should hash the file and record both junctions"
+    );
+
+    quarantine::restore(id).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(temp_folder_path.join("file")).unwrap(),
+        "contents"
+    );
+    let junction_path = temp_folder_path.join("junction");
+    assert!(
+        fs::symlink_metadata(&junction_path)
+            .unwrap()
+            .file_type()
+            .is_symlink(),
+        "Junction should be restored as a junction"
+    );
+    assert_eq!(
+        fs::read_to_string(junction_path.join("file")).unwrap(),
+        "outside"
+    );
+    assert!(
+        fs::symlink_metadata(temp_folder_path.join("dangling")).is_ok(),
+        "Dangling junction should be restored"
+    );
+}
+
+#[test]
+#[ignore = "mounting a virtual disk and creating symlinks need elevation"]
+fn quarantining_across_volumes_keeps_links_as_links() {
+    let mount_point = get_temporary_file_name();
+    let _mount_dir = create_temporary_directory(mount_point.clone());
+    let _disk = MountedVirtualDisk::mount(
+        get_temporary_file_name().with_extension("vhdx"),
+        &mount_point,
+    );
+    let target_path = get_temporary_file_name();
+    let _target_dir = create_temporary_directory(target_path.clone());
+    fs::write(target_path.join("file"), "outside").unwrap();
+
+    // The quarantine is on the system volume, so the tree is copied rather than renamed
+    let tree_path = mount_point.join("tree");
+    fs::create_dir(&tree_path).unwrap();
+    fs::write(tree_path.join("file"), "contents").unwrap();
+    create_junction(&tree_path.join("junction"), &target_path);
+    symlink_file(target_path.join("file"), tree_path.join("link")).unwrap();
+
+    let config = ForceOpsConfig {
+        quarantine: true,
+        disable_elevate: true,
+        ..ForceOpsConfig::default()
+    };
+    let deleter = FileAndDirectoryDeleter::new(config);
+    let report = deleter.delete_file_or_directory(&tree_path, false).unwrap();
+    assert!(!tree_path.exists(), "Tree should be quarantined");
+    let id = report.quarantined.get(&tree_path).unwrap();
+
+    quarantine::restore(id).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(tree_path.join("file")).unwrap(),
+        "contents"
+    );
+    for link in ["junction", "link"] {
+        assert!(
+            fs::symlink_metadata(tree_path.join(link))
+                .unwrap()
+                .file_type()
+                .is_symlink(),
+            "'{}' should be restored as a link",
+            link
+        );
+    }
+    assert_eq!(
+        fs::read_to_string(tree_path.join("junction").join("file")).unwrap(),
+        "outside"
+    );
+    assert_eq!(
+        fs::read_to_string(target_path.join("file")).unwrap(),
+        "outside",
+        "The targets of the links should be left alone"
+    );
+}
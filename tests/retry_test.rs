@@ -0,0 +1,68 @@
+use fops::retry::{Backoff, RetryPolicies, RetryPolicy};
+use std::io;
+use std::time::Duration;
+
+fn policy(backoff: Backoff) -> RetryPolicy {
+    RetryPolicy {
+        max_retries: 10,
+        initial_delay: Duration::from_millis(100),
+        backoff,
+        max_delay: Duration::from_millis(500),
+        jitter: 0.0,
+    }
+}
+
+#[test]
+fn fixed_backoff_waits_the_same_delay() {
+    let policy = policy(Backoff::Fixed);
+    assert_eq!(policy.delay(1), Duration::from_millis(100));
+    assert_eq!(policy.delay(5), Duration::from_millis(100));
+}
+
+#[test]
+fn linear_backoff_grows_up_to_the_cap() {
+    let policy = policy(Backoff::Linear);
+    assert_eq!(policy.delay(1), Duration::from_millis(100));
+    assert_eq!(policy.delay(3), Duration::from_millis(300));
+    assert_eq!(policy.delay(8), Duration::from_millis(500));
+}
+
+#[test]
+fn exponential_backoff_doubles_up_to_the_cap() {
+    let policy = policy(Backoff::Exponential);
+    assert_eq!(policy.delay(1), Duration::from_millis(100));
+    assert_eq!(policy.delay(2), Duration::from_millis(200));
+    assert_eq!(policy.delay(3), Duration::from_millis(400));
+    assert_eq!(policy.delay(40), Duration::from_millis(500));
+}
+
+#[test]
+fn jitter_only_shortens_the_delay() {
+    let policy = RetryPolicy {
+        jitter: 0.5,
+        ..policy(Backoff::Fixed)
+    };
+    for _ in 0..100 {
+        let delay = policy.delay(1);
+        assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(100));
+    }
+}
+
+#[test]
+fn policies_are_chosen_by_error_class() {
+    let policies = RetryPolicies {
+        locked: RetryPolicy::fixed(1, Duration::ZERO),
+        permission: RetryPolicy::fixed(2, Duration::ZERO),
+        racing: RetryPolicy::fixed(3, Duration::ZERO),
+    };
+
+    // ERROR_SHARING_VIOLATION, ERROR_ACCESS_DENIED, ERROR_DIR_NOT_EMPTY
+    let max_retries = |code| {
+        policies
+            .for_error(&io::Error::from_raw_os_error(code))
+            .max_retries
+    };
+    assert_eq!(max_retries(32), 1);
+    assert_eq!(max_retries(5), 2);
+    assert_eq!(max_retries(145), 3);
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::test_util::{create_temporary_directory, get_temporary_file_name};
+use fops::safety::{ProtectedPath, check_protected};
+use std::env;
+use std::path::{Path, PathBuf};
+
+#[test]
+fn drive_root_is_protected() {
+    let current_dir = env::current_dir().unwrap();
+    let root: PathBuf = current_dir.ancestors().last().unwrap().to_path_buf();
+    assert_eq!(check_protected(&root), Some(ProtectedPath::Root));
+    assert_eq!(
+        check_protected(Path::new(r"C:\")),
+        Some(ProtectedPath::Root)
+    );
+}
+
+#[test]
+fn home_and_its_ancestors_are_protected() {
+    let home = PathBuf::from(env::var_os("USERPROFILE").unwrap());
+    assert_eq!(check_protected(&home), Some(ProtectedPath::Home));
+    assert_eq!(
+        check_protected(home.parent().unwrap()),
+        Some(ProtectedPath::Home)
+    );
+}
+
+#[test]
+fn current_directory_and_its_ancestors_are_protected() {
+    let current_dir = env::current_dir().unwrap();
+    assert_eq!(
+        check_protected(&current_dir),
+        Some(ProtectedPath::CurrentDirectory)
+    );
+    // The parent may also be the home directory
+    assert!(check_protected(&current_dir.join("..")).is_some());
+}
+
+#[test]
+fn windows_directory_is_protected() {
+    let windows_dir = PathBuf::from(env::var_os("SystemRoot").unwrap());
+    assert_eq!(
+        check_protected(&windows_dir),
+        Some(ProtectedPath::WindowsDirectory)
+    );
+}
+
+#[test]
+fn ordinary_directories_are_not_protected() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+
+    assert_eq!(check_protected(&temp_folder_path), None);
+    assert_eq!(check_protected(&temp_folder_path.join("missing")), None);
+    assert_eq!(
+        check_protected(&env::current_dir().unwrap().join("src")),
+        None
+    );
+}
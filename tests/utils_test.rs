@@ -0,0 +1,47 @@
+mod common;
+
+use common::test_util::{create_junction, create_temporary_directory, get_temporary_file_name};
+use fops::utils::{combine_with_cwd_and_get_absolute_path, normalize_lexically};
+use std::env;
+use std::path::{Path, PathBuf};
+
+#[test]
+fn normalize_lexically_resolves_dots() {
+    assert_eq!(
+        normalize_lexically(Path::new(r"C:\a\.\b\..\c\")),
+        PathBuf::from(r"C:\a\c")
+    );
+    assert_eq!(
+        normalize_lexically(Path::new(r"C:\a\..\..\b")),
+        PathBuf::from(r"C:\b")
+    );
+}
+
+#[test]
+fn absolute_path_is_relative_to_current_directory() {
+    let current_dir = env::current_dir().unwrap();
+    assert_eq!(
+        combine_with_cwd_and_get_absolute_path(r".\src\..\Cargo.toml"),
+        current_dir.join("Cargo.toml")
+    );
+}
+
+#[test]
+fn absolute_path_does_not_follow_final_symlink() {
+    let temp_folder_path = get_temporary_file_name();
+    let _temp_dir = create_temporary_directory(temp_folder_path.clone());
+    let target_path = temp_folder_path.join("target");
+    std::fs::create_dir(&target_path).unwrap();
+    let junction_path = temp_folder_path.join("junction");
+    create_junction(&junction_path, &target_path);
+
+    let junction = junction_path.to_string_lossy().to_string();
+    assert_eq!(
+        combine_with_cwd_and_get_absolute_path(&junction),
+        junction_path
+    );
+    assert_eq!(
+        combine_with_cwd_and_get_absolute_path(&format!(r"{}\", junction)),
+        junction_path
+    );
+}